@@ -9,8 +9,11 @@ use calimero_storage::collections::{
 };
 use types::id;
 mod types;
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
+use unicode_normalization::UnicodeNormalization;
 
 id::define!(pub UserId<32, 44>);
 type MessageId = String;
@@ -81,6 +84,44 @@ pub struct MessageSentEvent {
     pub message_id: String,
 }
 
+/// Payload of `Event::Typing`. `parent_message_id` is the thread being
+/// typed in, or `None` for the channel timeline.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "calimero_sdk::serde")]
+#[borsh(crate = "calimero_sdk::borsh")]
+pub struct TypingEvent {
+    pub identity: String,
+    pub parent_message_id: Option<String>,
+}
+
+/// Emitted when an edit changes who a message mentions, so newly mentioned
+/// members can be notified and removed ones can drop the badge.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "calimero_sdk::serde")]
+#[borsh(crate = "calimero_sdk::borsh")]
+pub struct MentionsUpdatedEvent {
+    pub message_id: String,
+    pub added: Vec<UserId>,
+    pub removed: Vec<UserId>,
+    /// Whether the message mentions @everyone / @here after the edit.
+    pub broadcast: bool,
+}
+
+/// Members in `after` but not `before`, and in `before` but not `after`.
+fn mention_diff(before: &[UserId], after: &[UserId]) -> (Vec<UserId>, Vec<UserId>) {
+    let added = after
+        .iter()
+        .filter(|id| !before.contains(id))
+        .copied()
+        .collect();
+    let removed = before
+        .iter()
+        .filter(|id| !after.contains(id))
+        .copied()
+        .collect();
+    (added, removed)
+}
+
 #[derive(Debug, Clone, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
 #[borsh(crate = "calimero_sdk::borsh")]
 #[serde(crate = "calimero_sdk::serde")]
@@ -106,6 +147,8 @@ impl MergeableTrait for Attachment {
 }
 
 impl Attachment {
+    /// `media` is left empty: it lives in `MeroChat::attachment_media`, and
+    /// `MeroChat::attachments_public` fills it in.
     fn to_public(&self) -> AttachmentPublic {
         AttachmentPublic {
             name: self.name.clone(),
@@ -113,8 +156,96 @@ impl Attachment {
             size: self.size,
             blob_id: encode_blob_id_base58(&self.blob_id),
             uploaded_at: self.uploaded_at,
+            media: None,
+        }
+    }
+}
+
+/// Optional rendering hints for voice notes, video and images. Kept out of
+/// `Attachment` itself so attachments already stored keep their Borsh
+/// layout; see `MessageMedia`.
+#[derive(
+    Debug, Clone, Default, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize,
+)]
+#[borsh(crate = "calimero_sdk::borsh")]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct MediaMetadata {
+    pub duration_ms: Option<u64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Peak amplitudes (0-255) summarising a voice note, at most
+    /// `MEDIA_WAVEFORM_MAX_POINTS` of them.
+    pub waveform: Option<Vec<u8>>,
+    /// Base58 blob ID of a preview image, announced to the context.
+    pub thumbnail_blob_id: Option<String>,
+    pub alt_text: Option<String>,
+}
+
+/// The `MediaMetadata` of one message's attachments, by kind and position
+/// in its `files` / `images` lists. Only the message's own send or edit
+/// writes it.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+#[borsh(crate = "calimero_sdk::borsh")]
+struct MessageMedia {
+    files: Vec<Option<MediaMetadata>>,
+    images: Vec<Option<MediaMetadata>>,
+}
+
+impl MessageMedia {
+    fn from_inputs(
+        files: &Option<Vec<AttachmentInput>>,
+        images: &Option<Vec<AttachmentInput>>,
+    ) -> Self {
+        let media = |inputs: &Option<Vec<AttachmentInput>>| {
+            inputs
+                .iter()
+                .flatten()
+                .map(|input| input.media.clone())
+                .collect()
+        };
+        MessageMedia {
+            files: media(files),
+            images: media(images),
         }
     }
+
+    fn is_empty(&self) -> bool {
+        self.files.iter().chain(&self.images).all(Option::is_none)
+    }
+
+    fn get(&self, kind: AttachmentKind, index: usize) -> Option<MediaMetadata> {
+        let list = match kind {
+            AttachmentKind::Files => &self.files,
+            AttachmentKind::Images => &self.images,
+        };
+        list.get(index).cloned().flatten()
+    }
+}
+
+const MEDIA_WAVEFORM_MAX_POINTS: usize = 256;
+const MEDIA_ALT_TEXT_MAX_CHARS: usize = 1_000;
+
+fn validate_media(media: &MediaMetadata) -> Result<(), String> {
+    if media.width == Some(0) || media.height == Some(0) {
+        return Err("Media width and height must be greater than zero".to_string());
+    }
+    if media
+        .waveform
+        .as_ref()
+        .is_some_and(|w| w.len() > MEDIA_WAVEFORM_MAX_POINTS)
+    {
+        return Err(format!(
+            "Waveform cannot have more than {} points",
+            MEDIA_WAVEFORM_MAX_POINTS
+        ));
+    }
+    if let Some(ref alt_text) = media.alt_text {
+        validate_field_length("Alt text", alt_text, MEDIA_ALT_TEXT_MAX_CHARS)?;
+    }
+    if let Some(ref thumbnail) = media.thumbnail_blob_id {
+        parse_blob_id_base58(thumbnail)?;
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
@@ -126,6 +257,7 @@ pub struct AttachmentPublic {
     pub size: u64,
     pub blob_id: String,
     pub uploaded_at: u64,
+    pub media: Option<MediaMetadata>,
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
@@ -136,6 +268,7 @@ pub struct AttachmentInput {
     pub mime_type: String,
     pub size: u64,
     pub blob_id_str: String,
+    pub media: Option<MediaMetadata>,
 }
 
 #[app::event]
@@ -148,6 +281,14 @@ pub enum Event {
     InfoUpdated(),
     /// Payload: target identity (base58) whose role just changed.
     RoleUpdated(String),
+    /// Payload: ID of the thread root whose state just changed.
+    ThreadStateUpdated(String),
+    /// Payload: identity (base58) that joined or left the context.
+    MemberUpdated(String),
+    MentionsUpdated(MentionsUpdatedEvent),
+    /// Ephemeral: someone is typing. Clients should expire it on their own
+    /// after a few seconds; there is no matching "stopped typing" event.
+    Typing(TypingEvent),
 }
 
 /// "channel" or "dm" — stored in app state so it's mutable (supports renames).
@@ -185,6 +326,183 @@ impl Default for Role {
     }
 }
 
+/// Lifecycle of a thread, set with `set_thread_state`.
+///
+/// - `Open`      default; anyone may reply
+/// - `Resolved`  answered; replies still allowed, clients may hide it
+/// - `Locked`    no further replies; only Mods/Admins can change it back
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+)]
+#[borsh(crate = "calimero_sdk::borsh")]
+#[serde(crate = "calimero_sdk::serde")]
+pub enum ThreadState {
+    #[default]
+    Open,
+    Resolved,
+    Locked,
+}
+
+/// Who may use the broadcast mentions @everyone and @here in a channel.
+/// In DMs both members may always use them.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+)]
+#[borsh(crate = "calimero_sdk::borsh")]
+#[serde(crate = "calimero_sdk::serde")]
+pub enum BroadcastMentionPolicy {
+    Everyone,
+    #[default]
+    Moderators,
+    Admins,
+}
+
+/// Usernames that address the whole context rather than one member.
+const BROADCAST_MENTIONS: [&str; 2] = ["everyone", "here"];
+
+/// Raw `@mention`, `#hashtag` and `#<context id>` tokens found in message
+/// text by `parse_message_refs`, in order of first appearance.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct MessageRefs {
+    mentions: Vec<String>,
+    hashtags: Vec<String>,
+    channels: Vec<String>,
+}
+
+fn is_ref_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// Whether `text` still mentions `username` as a whole `@handle`: not glued
+/// to a preceding word and not running on into a longer name, with the same
+/// trailing `.`/`-` trimming as `parse_message_refs`. Case-insensitive.
+fn mentions_handle(text: &str, username: &str) -> bool {
+    let text = text.to_lowercase();
+    let handle = format!("@{}", username.to_lowercase());
+    text.match_indices(&handle).any(|(start, _)| {
+        let glued = text[..start].chars().next_back().is_some_and(is_ref_char);
+        let rest = text[start + handle.len()..].trim_start_matches(['.', '-']);
+        !glued && !rest.starts_with(is_ref_char)
+    })
+}
+
+/// Scan `text` for references. A token starts at `@` or `#` that is not
+/// glued to a preceding word (so `a@b.com` is not a mention) and runs over
+/// letters, digits, `_`, `-` and `.`, minus trailing punctuation. Inline
+/// code between backticks is skipped. A `#` token that decodes as a 32-byte
+/// base58 ID is a channel reference; other `#` tokens are hashtags unless
+/// they are all digits (`#123`).
+fn parse_message_refs(text: &str) -> MessageRefs {
+    let mut refs = MessageRefs::default();
+    let chars: Vec<char> = text.chars().collect();
+    let mut in_code = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '`' {
+            in_code = !in_code;
+            i += 1;
+            continue;
+        }
+        let glued = i > 0 && is_ref_char(chars[i - 1]);
+        if in_code || glued || (c != '@' && c != '#') {
+            i += 1;
+            continue;
+        }
+
+        let start = i + 1;
+        let mut end = start;
+        while end < chars.len() && is_ref_char(chars[end]) {
+            end += 1;
+        }
+        i = end;
+        let token: String = chars[start..end].iter().collect();
+        let token = token.trim_end_matches(['.', '-']);
+        if token.is_empty() {
+            continue;
+        }
+
+        if c == '@' {
+            if !refs.mentions.iter().any(|m| m == token) {
+                refs.mentions.push(token.to_string());
+            }
+        } else if parse_blob_id_base58(token).is_ok() {
+            if !refs.channels.iter().any(|ch| ch == token) {
+                refs.channels.push(token.to_string());
+            }
+        } else if !token.chars().all(|ch| ch.is_ascii_digit()) {
+            let tag = token.to_lowercase();
+            if !refs.hashtags.contains(&tag) {
+                refs.hashtags.push(tag);
+            }
+        }
+    }
+    refs
+}
+
+/// `#hashtags` (lowercased) and `#<context id>` references parsed from a
+/// message body, stored in `MeroChat::message_tags`.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+#[borsh(crate = "calimero_sdk::borsh")]
+struct MessageTags {
+    hashtags: Vec<String>,
+    channel_refs: Vec<String>,
+}
+
+/// Everything derived from one message body. Mentions are written onto a
+/// `Message` with `apply_to`; `tags` go to the side table.
+struct MessageMarkup {
+    mentions: Vec<UserId>,
+    mentions_usernames: Vec<String>,
+    tags: MessageTags,
+}
+
+impl MessageMarkup {
+    fn has_broadcast(&self) -> bool {
+        self.mentions_usernames
+            .iter()
+            .any(|name| BROADCAST_MENTIONS.contains(&name.as_str()))
+    }
+
+    fn apply_to(&self, message: &mut Message) {
+        let mut mentions = UnorderedSet::new();
+        for id in &self.mentions {
+            let _ = mentions.insert(*id);
+        }
+        let mut mentions_usernames = Vector::new();
+        for name in &self.mentions_usernames {
+            let _ = mentions_usernames.push(LwwRegister::new(name.clone()));
+        }
+        message.mentions = mentions;
+        message.mentions_usernames = mentions_usernames;
+    }
+}
+
+/// A stored chat message. Later per-message metadata (thread broadcasts,
+/// inline replies, forward provenance, hashtags, attachment media) lives in
+/// side tables on `MeroChat` so this Borsh layout stays readable from
+/// existing state. Serializing a `Message` directly leaves it out, including
+/// each attachment's `media`; the query endpoints return it as part of
+/// `MessageWithReactions`.
 #[derive(BorshDeserialize, BorshSerialize)]
 #[borsh(crate = "calimero_sdk::borsh")]
 pub struct Message {
@@ -334,7 +652,38 @@ pub struct MessageWithReactions {
     pub deleted: Option<bool>,
     pub thread_count: u32,
     pub thread_last_timestamp: u64,
+    pub thread_state: ThreadState,
     pub parent_message_id: Option<String>,
+    pub reply_to: Option<ReplyPreview>,
+    pub forwarded_from: Option<ForwardProvenance>,
+    pub hashtags: Vec<String>,
+    pub channel_refs: Vec<String>,
+}
+
+/// Compact preview of the message an inline reply points at. `sender` is
+/// `None` when the target hasn't synced to this node (or never existed);
+/// `deleted` targets keep their author but drop the snippet.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "calimero_sdk::serde")]
+#[borsh(crate = "calimero_sdk::borsh")]
+pub struct ReplyPreview {
+    pub message_id: MessageId,
+    pub sender: Option<UserId>,
+    pub sender_username: Option<String>,
+    pub snippet: String,
+    pub deleted: bool,
+}
+
+const REPLY_SNIPPET_MAX_CHARS: usize = 120;
+
+/// First `max_chars` characters of `text`, with an ellipsis when cut.
+fn text_snippet(text: &str, max_chars: usize) -> String {
+    let mut chars = text.chars();
+    let mut snippet: String = chars.by_ref().take(max_chars).collect();
+    if chars.next().is_some() {
+        snippet.push('…');
+    }
+    snippet
 }
 
 fn attachments_vector_to_public(vector: &Vector<Attachment>) -> Vec<AttachmentPublic> {
@@ -347,6 +696,337 @@ fn attachments_vector_to_public(vector: &Vector<Attachment>) -> Vec<AttachmentPu
     attachments
 }
 
+/// Context-level limits on message attachments, set by admins with
+/// `set_attachment_policy`. Sizes are the client-reported ones; the WASM
+/// cannot read blob lengths itself.
+#[derive(Debug, Clone, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "calimero_sdk::borsh")]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct AttachmentPolicy {
+    /// Exact types (`application/pdf`) or `type/*` wildcards. Empty allows
+    /// any type.
+    pub allowed_mime_types: Vec<String>,
+    pub max_file_size: u64,
+    /// Files and images together.
+    pub max_attachments_per_message: u32,
+    /// Total bytes each member may have attached across the context.
+    pub user_quota_bytes: u64,
+}
+
+impl Default for AttachmentPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_mime_types: Vec::new(),
+            max_file_size: 25 * 1024 * 1024,
+            max_attachments_per_message: 10,
+            user_quota_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+impl AttachmentPolicy {
+    fn allows_mime_type(&self, mime_type: &str) -> bool {
+        if self.allowed_mime_types.is_empty() {
+            return true;
+        }
+        let mime_type = mime_type.trim().to_ascii_lowercase();
+        self.allowed_mime_types.iter().any(|allowed| {
+            let allowed = allowed.trim().to_ascii_lowercase();
+            match allowed.strip_suffix("/*") {
+                Some(family) => mime_type.split('/').next() == Some(family),
+                None => allowed == mime_type,
+            }
+        })
+    }
+
+    /// Check one message's attachments against the policy, given what the
+    /// sender has already used. Returns the bytes they add on success.
+    fn check(&self, attachments: &[&AttachmentInput], used_bytes: u64) -> Result<u64, String> {
+        if attachments.len() > self.max_attachments_per_message as usize {
+            return Err(format!(
+                "A message can have at most {} attachments",
+                self.max_attachments_per_message
+            ));
+        }
+        let mut total: u64 = 0;
+        for attachment in attachments {
+            if !self.allows_mime_type(&attachment.mime_type) {
+                return Err(format!(
+                    "'{}' has a file type that isn't allowed here ({})",
+                    attachment.name, attachment.mime_type
+                ));
+            }
+            if attachment.size > self.max_file_size {
+                return Err(format!(
+                    "'{}' is larger than the {} byte limit",
+                    attachment.name, self.max_file_size
+                ));
+            }
+            if let Some(ref media) = attachment.media {
+                validate_media(media).map_err(|e| format!("'{}': {}", attachment.name, e))?;
+            }
+            total = total.saturating_add(attachment.size);
+        }
+        if used_bytes.saturating_add(total) > self.user_quota_bytes {
+            return Err(format!(
+                "Attachment quota exceeded: {} of {} bytes used",
+                used_bytes, self.user_quota_bytes
+            ));
+        }
+        Ok(total)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(crate = "calimero_sdk::serde")]
+pub enum AttachmentKind {
+    Files,
+    Images,
+}
+
+/// One attachment in the `get_attachments` gallery, with the message it
+/// was posted in. `index` is its position in that message's list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct AttachmentEntry {
+    pub attachment: AttachmentPublic,
+    pub kind: AttachmentKind,
+    pub index: u32,
+    pub message_id: MessageId,
+    /// Thread root when the attachment was posted as a thread reply.
+    pub parent_message_id: Option<MessageId>,
+    pub sender: UserId,
+    pub sender_username: String,
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct AttachmentPage {
+    pub attachments: Vec<AttachmentEntry>,
+    /// Pass back as `cursor` for the next page; `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+const ATTACHMENTS_DEFAULT_LIMIT: usize = 50;
+const ATTACHMENTS_MAX_LIMIT: usize = 200;
+
+/// Gallery order is newest first; ties break on message ID, kind and index
+/// so the order (and therefore the cursor) is stable across nodes.
+fn attachment_sort_key(entry: &AttachmentEntry) -> (Reverse<u64>, &str, AttachmentKind, u32) {
+    (
+        Reverse(entry.timestamp),
+        entry.message_id.as_str(),
+        entry.kind,
+        entry.index,
+    )
+}
+
+fn attachment_cursor(entry: &AttachmentEntry) -> String {
+    let kind = match entry.kind {
+        AttachmentKind::Files => "f",
+        AttachmentKind::Images => "i",
+    };
+    format!(
+        "{}:{}:{}:{}",
+        entry.timestamp, kind, entry.index, entry.message_id
+    )
+}
+
+/// Sort `entries` into gallery order and return the page after `cursor`.
+/// The cursor is a position, not an offset, so attachments posted between
+/// two calls don't shift the next page.
+fn paginate_attachments(
+    mut entries: Vec<AttachmentEntry>,
+    limit: Option<usize>,
+    cursor: Option<&str>,
+) -> Result<AttachmentPage, String> {
+    entries.sort_by(|a, b| attachment_sort_key(a).cmp(&attachment_sort_key(b)));
+
+    let start = match cursor {
+        Some(cursor) => {
+            let invalid = || format!("Invalid cursor '{}'", cursor);
+            let mut parts = cursor.splitn(4, ':');
+            let timestamp: u64 = parts
+                .next()
+                .and_then(|p| p.parse().ok())
+                .ok_or_else(invalid)?;
+            let kind = match parts.next() {
+                Some("f") => AttachmentKind::Files,
+                Some("i") => AttachmentKind::Images,
+                _ => return Err(invalid()),
+            };
+            let index: u32 = parts
+                .next()
+                .and_then(|p| p.parse().ok())
+                .ok_or_else(invalid)?;
+            let message_id = parts.next().ok_or_else(invalid)?;
+            let key = (Reverse(timestamp), message_id, kind, index);
+            entries.partition_point(|entry| attachment_sort_key(entry) <= key)
+        }
+        None => 0,
+    };
+
+    let limit = limit
+        .unwrap_or(ATTACHMENTS_DEFAULT_LIMIT)
+        .clamp(1, ATTACHMENTS_MAX_LIMIT);
+    let page: Vec<AttachmentEntry> = entries.into_iter().skip(start).take(limit + 1).collect();
+    let (attachments, next_cursor) = if page.len() > limit {
+        let attachments: Vec<AttachmentEntry> = page.into_iter().take(limit).collect();
+        let next = attachments.last().map(attachment_cursor);
+        (attachments, next)
+    } else {
+        (page, None)
+    };
+    Ok(AttachmentPage {
+        attachments,
+        next_cursor,
+    })
+}
+
+/// Attachment changes for `edit_message`. New attachments go through the
+/// same policy check and announce path as `send_message`; removals are by
+/// blob ID and apply to files and images alike.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct AttachmentEdit {
+    pub add_files: Option<Vec<AttachmentInput>>,
+    pub add_images: Option<Vec<AttachmentInput>>,
+    pub remove_blob_ids: Option<Vec<String>>,
+}
+
+/// An `AttachmentEdit` after validation and announcing, ready to apply.
+#[derive(Default)]
+struct AttachmentChanges {
+    add_files: Vec<Attachment>,
+    add_images: Vec<Attachment>,
+    added_media: MessageMedia,
+    removed: Vec<AttachmentPublic>,
+}
+
+impl AttachmentChanges {
+    fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.add_files.is_empty() && self.add_images.is_empty()
+    }
+
+    fn added(&self) -> Vec<AttachmentPublic> {
+        self.add_files
+            .iter()
+            .chain(&self.add_images)
+            .map(Attachment::to_public)
+            .collect()
+    }
+
+    fn apply_to(&self, message: &mut Message) {
+        message.files = self.apply_to_list(&message.files, &self.add_files);
+        message.images = self.apply_to_list(&message.images, &self.add_images);
+    }
+
+    /// `media` re-indexed the way `apply_to` rearranges `message`'s lists:
+    /// survivors keep their order and new attachments are appended.
+    fn apply_to_media(&self, message: &Message, media: &MessageMedia) -> MessageMedia {
+        let list = |existing: &Vector<Attachment>, kind, added: &[Option<MediaMetadata>]| {
+            let mut list: Vec<Option<MediaMetadata>> = Vec::new();
+            if let Ok(iter) = existing.iter() {
+                for (index, attachment) in iter.enumerate() {
+                    let blob_id = encode_blob_id_base58(&attachment.blob_id);
+                    if !self.removed.iter().any(|r| r.blob_id == blob_id) {
+                        list.push(media.get(kind, index));
+                    }
+                }
+            }
+            list.extend(added.iter().cloned());
+            list
+        };
+        MessageMedia {
+            files: list(
+                &message.files,
+                AttachmentKind::Files,
+                &self.added_media.files,
+            ),
+            images: list(
+                &message.images,
+                AttachmentKind::Images,
+                &self.added_media.images,
+            ),
+        }
+    }
+
+    fn apply_to_list(
+        &self,
+        existing: &Vector<Attachment>,
+        added: &[Attachment],
+    ) -> Vector<Attachment> {
+        let mut list = Vector::new();
+        if let Ok(iter) = existing.iter() {
+            for attachment in iter {
+                let blob_id = encode_blob_id_base58(&attachment.blob_id);
+                if !self.removed.iter().any(|r| r.blob_id == blob_id) {
+                    let _ = list.push(attachment);
+                }
+            }
+        }
+        for attachment in added {
+            let _ = list.push(attachment.clone());
+        }
+        list
+    }
+}
+
+/// Pick the attachments named by `blob_ids` out of a message's current
+/// ones. Every ID must match, so a stale client can't silently no-op.
+fn attachments_to_remove(
+    current: &[AttachmentPublic],
+    blob_ids: &[String],
+) -> Result<Vec<AttachmentPublic>, String> {
+    let mut removed = Vec::new();
+    for blob_id in blob_ids {
+        let attachment = current
+            .iter()
+            .find(|attachment| attachment.blob_id == *blob_id)
+            .ok_or_else(|| format!("Attachment {} is not on this message", blob_id))?;
+        if !removed
+            .iter()
+            .any(|r: &AttachmentPublic| r.blob_id == *blob_id)
+        {
+            removed.push(attachment.clone());
+        }
+    }
+    Ok(removed)
+}
+
+/// One entry in a message's edit history: what it looked like before the
+/// edit at `edited_at` (the client timestamp passed to `edit_message`) and
+/// which attachments the edit added or removed.
+#[derive(Debug, Clone, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "calimero_sdk::borsh")]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct MessageEdit {
+    pub edited_at: u64,
+    pub previous_text: String,
+    pub added_attachments: Vec<AttachmentPublic>,
+    pub removed_attachments: Vec<AttachmentPublic>,
+}
+
+impl MergeableTrait for MessageEdit {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        if other.edited_at > self.edited_at {
+            *self = other.clone();
+        }
+        Ok(())
+    }
+}
+
+/// A member's attachment storage, as returned by `get_attachment_usage`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct AttachmentUsage {
+    pub used_bytes: u64,
+    pub quota_bytes: u64,
+}
+
+/// Announce each blob to this context and convert the inputs. A failed
+/// announce is an error: other members could never fetch the blob.
 fn attachment_inputs_to_vector(
     inputs: Option<Vec<AttachmentInput>>,
     context_id: &[u8; 32],
@@ -358,12 +1038,10 @@ fn attachment_inputs_to_vector(
             let blob_id = parse_blob_id_base58(&attachment_input.blob_id_str)?;
 
             if !env::blob_announce_to_context(&blob_id, context_id) {
-                let context_b58 = encode_blob_id_base58(context_id);
-                app::log!(
-                    "Warning: failed to announce blob {} to context {}",
-                    attachment_input.blob_id_str,
-                    context_b58
-                );
+                return Err(format!(
+                    "Failed to announce blob {} to this context",
+                    attachment_input.blob_id_str
+                ));
             }
 
             let attachment = Attachment {
@@ -381,6 +1059,96 @@ fn attachment_inputs_to_vector(
     Ok(vector)
 }
 
+/// Origin of a forwarded message. Contexts are separate app instances, so
+/// the source context produces this record (`get_forward_payload`) and the
+/// client hands it to `forward_message` in the target context.
+///
+/// The target context cannot read the source, so across contexts every field
+/// here is the client's word. `verified` is only set when a message is
+/// forwarded within its own context, where `forward_message` rebuilds the
+/// record from the original. `digest` is an unkeyed SHA-256 over the payload:
+/// it catches accidental corruption, not a client that rewrites the payload
+/// and recomputes it.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "calimero_sdk::serde")]
+#[borsh(crate = "calimero_sdk::borsh")]
+pub struct ForwardProvenance {
+    pub source_context_id: String,
+    pub original_message_id: MessageId,
+    pub original_sender: UserId,
+    pub original_sender_username: String,
+    pub original_timestamp: u64,
+    pub digest: String,
+    /// Set by `forward_message`; ignored on input.
+    #[serde(default)]
+    pub verified: bool,
+}
+
+/// Everything the target context needs to re-create a forwarded message.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct ForwardPayload {
+    pub provenance: ForwardProvenance,
+    pub text: String,
+    pub files: Vec<AttachmentPublic>,
+    pub images: Vec<AttachmentPublic>,
+}
+
+impl ForwardPayload {
+    /// Covers every field except `digest` and `verified`.
+    fn compute_digest(&self) -> String {
+        let p = &self.provenance;
+        let mut hasher = Sha256::new();
+        // Length-prefix each field so boundaries can't be shifted.
+        let mut field = |bytes: &[u8]| {
+            hasher.update((bytes.len() as u64).to_be_bytes());
+            hasher.update(bytes);
+        };
+        field(p.source_context_id.as_bytes());
+        field(p.original_message_id.as_bytes());
+        field(p.original_sender.as_ref());
+        field(p.original_sender_username.as_bytes());
+        field(&p.original_timestamp.to_be_bytes());
+        field(self.text.as_bytes());
+        for (kind, list) in [("files", &self.files), ("images", &self.images)] {
+            field(kind.as_bytes());
+            field(&(list.len() as u64).to_be_bytes());
+            for attachment in list {
+                field(attachment.name.as_bytes());
+                field(attachment.mime_type.as_bytes());
+                field(&attachment.size.to_be_bytes());
+                field(attachment.blob_id.as_bytes());
+                field(&attachment.uploaded_at.to_be_bytes());
+                field(&calimero_sdk::borsh::to_vec(&attachment.media).unwrap_or_default());
+            }
+        }
+
+        let mut s = String::with_capacity(64);
+        for b in hasher.finalize() {
+            write!(&mut s, "{:02x}", b).unwrap();
+        }
+        s
+    }
+}
+
+fn attachments_public_to_inputs(attachments: &[AttachmentPublic]) -> Option<Vec<AttachmentInput>> {
+    if attachments.is_empty() {
+        return None;
+    }
+    Some(
+        attachments
+            .iter()
+            .map(|a| AttachmentInput {
+                name: a.name.clone(),
+                mime_type: a.mime_type.clone(),
+                size: a.size,
+                blob_id_str: a.blob_id.clone(),
+                media: a.media.clone(),
+            })
+            .collect(),
+    )
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "calimero_sdk::serde")]
 pub struct FullMessageResponse {
@@ -389,6 +1157,181 @@ pub struct FullMessageResponse {
     pub start_position: u32,
 }
 
+/// The caller's unread state across the channel and threads; see
+/// `get_unread_summary`.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct UnreadSummary {
+    /// Unread channel messages.
+    pub channel_count: u32,
+    /// Unread replies in threads the caller follows or participates in.
+    pub thread_count: u32,
+    /// Unread mentions of the caller, in the channel or any thread.
+    pub mention_count: u32,
+    /// Unread messages, channel or thread, matching the caller's keywords.
+    pub alert_count: u32,
+    /// Oldest message counted above, channel or thread.
+    pub oldest_unread_id: Option<MessageId>,
+}
+
+/// How much of a context's activity should reach a member's badges.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Default,
+    BorshDeserialize,
+    BorshSerialize,
+    Serialize,
+    Deserialize,
+)]
+#[borsh(crate = "calimero_sdk::borsh")]
+#[serde(crate = "calimero_sdk::serde")]
+pub enum NotifyLevel {
+    #[default]
+    All,
+    Mentions,
+    None,
+}
+
+/// Per-user notification preferences, as read and written by
+/// `get_my_notification_settings` / `set_my_notification_settings`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct NotificationSettings {
+    pub level: NotifyLevel,
+    /// Unix seconds, like message timestamps; compared against node time.
+    /// While in the future, behaves like `None`.
+    pub muted_until: Option<u64>,
+    /// When off, followed-thread replies stop counting as unread. Mentions
+    /// in threads still count.
+    pub thread_notifications: bool,
+    pub keywords: Vec<String>,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            level: NotifyLevel::All,
+            muted_until: None,
+            thread_notifications: true,
+            keywords: Vec::new(),
+        }
+    }
+}
+
+impl NotificationSettings {
+    fn is_muted(&self, now: u64) -> bool {
+        self.muted_until.is_some_and(|until| until > now)
+    }
+}
+
+/// Stored form of `NotificationSettings`. Only the owner writes it, and it
+/// replicates to their other devices like any other state.
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "calimero_sdk::borsh")]
+pub struct StoredNotificationSettings {
+    pub level: LwwRegister<NotifyLevel>,
+    pub muted_until: LwwRegister<Option<u64>>,
+    pub thread_notifications: LwwRegister<bool>,
+    pub keywords: LwwRegister<Vec<String>>,
+}
+
+impl MergeableTrait for StoredNotificationSettings {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        MergeableTrait::merge(&mut self.level, &other.level)?;
+        MergeableTrait::merge(&mut self.muted_until, &other.muted_until)?;
+        MergeableTrait::merge(&mut self.thread_notifications, &other.thread_notifications)?;
+        MergeableTrait::merge(&mut self.keywords, &other.keywords)?;
+        Ok(())
+    }
+}
+
+const NOTIFY_KEYWORDS_MAX: usize = 20;
+const NOTIFY_KEYWORD_MAX_CHARS: usize = 64;
+
+/// Trim, lowercase (matching `message_matches_search`) and de-duplicate
+/// keyword alerts. Blank entries are dropped.
+fn normalize_keywords(keywords: Vec<String>) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for keyword in keywords {
+        let keyword = keyword.trim().to_lowercase();
+        if keyword.is_empty() || normalized.contains(&keyword) {
+            continue;
+        }
+        validate_field_length("Keyword", &keyword, NOTIFY_KEYWORD_MAX_CHARS)?;
+        normalized.push(keyword);
+    }
+    if normalized.len() > NOTIFY_KEYWORDS_MAX {
+        return Err(format!(
+            "At most {} keywords are allowed",
+            NOTIFY_KEYWORDS_MAX
+        ));
+    }
+    Ok(normalized)
+}
+
+/// Case-insensitive substring match against normalized keywords, the same
+/// comparison `message_matches_search` does for search terms.
+fn text_matches_keywords(text: &str, keywords: &[String]) -> bool {
+    if keywords.is_empty() {
+        return false;
+    }
+    let text = text.to_lowercase();
+    keywords
+        .iter()
+        .any(|keyword| text.contains(keyword.as_str()))
+}
+
+/// Zero out the parts of `summary` the user asked not to be notified about.
+/// Keyword alerts behave like mentions.
+/// `oldest_unread_id` is left alone: it's a jump target, not a notification.
+fn apply_notification_settings(
+    mut summary: UnreadSummary,
+    settings: &NotificationSettings,
+    now: u64,
+) -> UnreadSummary {
+    let level = if settings.is_muted(now) {
+        NotifyLevel::None
+    } else {
+        settings.level
+    };
+    match level {
+        NotifyLevel::All => {}
+        NotifyLevel::Mentions => {
+            summary.channel_count = 0;
+            summary.thread_count = 0;
+        }
+        NotifyLevel::None => {
+            summary.channel_count = 0;
+            summary.thread_count = 0;
+            summary.mention_count = 0;
+            summary.alert_count = 0;
+        }
+    }
+    if !settings.thread_notifications {
+        summary.thread_count = 0;
+    }
+    summary
+}
+
+/// Per-thread metadata returned by `get_thread_summary` and
+/// `get_followed_threads_with_unread`. Unread fields are relative to the caller.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct ThreadSummary {
+    pub parent_message_id: MessageId,
+    pub reply_count: u32,
+    pub last_reply_timestamp: u64,
+    pub participants: Vec<UserId>,
+    pub thread_state: ThreadState,
+    pub following: bool,
+    pub last_read_timestamp: u64,
+    pub unread_count: u32,
+}
+
 /// Per-context metadata returned by `get_info` / `get_channel_info`.
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "calimero_sdk::serde")]
@@ -398,6 +1341,8 @@ pub struct ContextInfo {
     pub description: String,
     pub created_at: u64,
     pub creator: String,
+    pub read_receipts_enabled: bool,
+    pub broadcast_mention_policy: BroadcastMentionPolicy,
 }
 
 /// Per-context user profile returned by `get_profiles`.
@@ -406,29 +1351,396 @@ pub struct ContextInfo {
 pub struct UserProfile {
     pub identity: UserId,
     pub username: String,
-    pub avatar: Option<String>,
+    pub avatar: Option<AttachmentPublic>,
+    /// Free-form avatar string set before avatars were blobs. Only reported
+    /// while the member has never set or removed a blob avatar.
+    pub legacy_avatar: Option<String>,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub pronouns: Option<String>,
+    pub timezone: Option<String>,
+    /// `None` when never set, cleared, or expired.
+    pub status: Option<CustomStatus>,
+    /// Earlier usernames, oldest first. Lets clients resolve `@oldname` in
+    /// message text written before a `change_username`.
+    pub previous_usernames: Vec<String>,
+    /// Another member registered the same username concurrently and won the
+    /// tie-break. Clients should show a disambiguator (e.g. a short ID).
+    pub duplicate_username: bool,
 }
 
-/// Per-context profile stored in CRDT state.
-#[derive(BorshDeserialize, BorshSerialize)]
+/// One entry in a member's username history: the name they had before
+/// `changed_at` (node time, nanoseconds).
+#[derive(Debug, Clone, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
 #[borsh(crate = "calimero_sdk::borsh")]
-pub struct StoredProfile {
-    pub username: LwwRegister<String>,
-    pub avatar: Option<LwwRegister<String>>,
+#[serde(crate = "calimero_sdk::serde")]
+pub struct UsernameChange {
+    pub previous_username: String,
+    pub changed_at: u64,
 }
 
-impl MergeableTrait for StoredProfile {
+impl MergeableTrait for UsernameChange {
     fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
-        MergeableTrait::merge(&mut self.username, &other.username)?;
-        if let (Some(ref mut a), Some(ref b)) = (&mut self.avatar, &other.avatar) {
-            MergeableTrait::merge(a, b)?;
-        } else if other.avatar.is_some() {
-            self.avatar = other.avatar.clone();
+        if other.changed_at > self.changed_at {
+            *self = other.clone();
+        }
+        Ok(())
+    }
+}
+
+const USERNAME_MAX_LEN: usize = 50;
+/// Minimum time between two `change_username` calls (7 days, in nanoseconds).
+const USERNAME_CHANGE_COOLDOWN_NS: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+/// Key used for username uniqueness: NFKC, case-folded, re-normalized and
+/// trimmed, so "Alice", "ALICE" and "Ａｌｉｃｅ" all collide.
+fn normalize_username(username: &str) -> String {
+    let folded = username.nfkc().collect::<String>().to_lowercase();
+    folded.nfkc().collect::<String>().trim().to_string()
+}
+
+fn validate_username(username: &str) -> Result<(), String> {
+    if username.trim().is_empty() {
+        return Err("Username cannot be empty".to_string());
+    }
+    if username.len() > USERNAME_MAX_LEN {
+        return Err(format!(
+            "Username cannot be longer than {} characters",
+            USERNAME_MAX_LEN
+        ));
+    }
+    // `@everyone` / `@here` must always mean a broadcast.
+    if BROADCAST_MENTIONS.contains(&normalize_username(username).as_str()) {
+        return Err(format!("'{}' is a reserved name", username.trim()));
+    }
+    Ok(())
+}
+
+/// Per-context profile stored in CRDT state. Fields added after launch live
+/// in `ProfileDetails` so profiles already stored keep decoding.
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "calimero_sdk::borsh")]
+pub struct StoredProfile {
+    pub username: LwwRegister<String>,
+    /// Free-form avatar from before avatars were blobs. No longer written;
+    /// see `ProfileDetails::avatar`.
+    pub avatar: Option<LwwRegister<String>>,
+}
+
+impl StoredProfile {
+    fn new(username: String) -> Self {
+        StoredProfile {
+            username: LwwRegister::new(username),
+            avatar: None,
         }
+    }
+}
+
+/// Optional profile fields, kept beside `StoredProfile` in
+/// `MeroChat::profile_details`. Every field is its own register so edits to
+/// different fields from different devices merge instead of overwriting
+/// each other; `None` means never set.
+#[derive(Default, BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "calimero_sdk::borsh")]
+pub struct ProfileDetails {
+    /// Announced image blob. The inner `None` is an explicit removal, so it
+    /// wins over an older avatar under LWW instead of being merged away.
+    pub avatar: Option<LwwRegister<Option<Attachment>>>,
+    pub display_name: Option<LwwRegister<String>>,
+    pub bio: Option<LwwRegister<String>>,
+    pub pronouns: Option<LwwRegister<String>>,
+    /// IANA zone name, e.g. "Europe/Berlin".
+    pub timezone: Option<LwwRegister<String>>,
+    pub status: Option<LwwRegister<CustomStatus>>,
+}
+
+fn merge_optional<M: MergeableTrait + Clone>(
+    a: &mut Option<M>,
+    b: &Option<M>,
+) -> Result<(), MergeError> {
+    if let (Some(ref mut a), Some(ref b)) = (&mut *a, b) {
+        MergeableTrait::merge(a, b)?;
+    } else if b.is_some() {
+        *a = b.clone();
+    }
+    Ok(())
+}
+
+impl MergeableTrait for StoredProfile {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        MergeableTrait::merge(&mut self.username, &other.username)?;
+        merge_optional(&mut self.avatar, &other.avatar)?;
+        Ok(())
+    }
+}
+
+impl MergeableTrait for ProfileDetails {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        merge_optional(&mut self.avatar, &other.avatar)?;
+        merge_optional(&mut self.display_name, &other.display_name)?;
+        merge_optional(&mut self.bio, &other.bio)?;
+        merge_optional(&mut self.pronouns, &other.pronouns)?;
+        merge_optional(&mut self.timezone, &other.timezone)?;
+        merge_optional(&mut self.status, &other.status)?;
+        Ok(())
+    }
+}
+
+/// Custom status shown next to a member's name. An empty status means
+/// "cleared"; `expires_at` (Unix seconds, like message timestamps) hides it
+/// once node time passes it.
+#[derive(Debug, Clone, Default, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[borsh(crate = "calimero_sdk::borsh")]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct CustomStatus {
+    pub emoji: String,
+    pub text: String,
+    pub expires_at: Option<u64>,
+}
+
+impl CustomStatus {
+    fn is_active(&self, now: u64) -> bool {
+        let empty = self.emoji.is_empty() && self.text.is_empty();
+        !empty && self.expires_at.is_none_or(|at| at > now)
+    }
+}
+
+const AVATAR_MAX_SIZE: u64 = 2 * 1024 * 1024;
+const AVATAR_MIME_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// Validate an avatar upload and announce its blob to this context. As with
+/// message attachments, a failed announce is an error: a profile pointing at
+/// a blob nobody else can fetch is worse than no avatar.
+fn avatar_input_to_attachment(
+    input: AttachmentInput,
+    context_id: &[u8; 32],
+) -> Result<Attachment, String> {
+    if !AVATAR_MIME_TYPES.contains(&input.mime_type.as_str()) {
+        return Err(format!(
+            "Avatar must be one of {}; got '{}'",
+            AVATAR_MIME_TYPES.join(", "),
+            input.mime_type
+        ));
+    }
+    if input.size == 0 || input.size > AVATAR_MAX_SIZE {
+        return Err(format!(
+            "Avatar must be between 1 byte and {} bytes; got {}",
+            AVATAR_MAX_SIZE, input.size
+        ));
+    }
+
+    let blob_id = parse_blob_id_base58(&input.blob_id_str)?;
+    if !env::blob_announce_to_context(&blob_id, context_id) {
+        return Err(format!(
+            "Failed to announce avatar blob {} to this context",
+            input.blob_id_str
+        ));
+    }
+
+    Ok(Attachment {
+        name: input.name,
+        mime_type: input.mime_type,
+        size: input.size,
+        blob_id,
+        uploaded_at: env::time_now(),
+    })
+}
+
+const DISPLAY_NAME_MAX_CHARS: usize = 64;
+const BIO_MAX_CHARS: usize = 500;
+const PRONOUNS_MAX_CHARS: usize = 32;
+const STATUS_TEXT_MAX_CHARS: usize = 100;
+const STATUS_EMOJI_MAX_CHARS: usize = 16;
+
+fn validate_field_length(field: &str, value: &str, max_chars: usize) -> Result<(), String> {
+    if value.chars().count() > max_chars {
+        return Err(format!(
+            "{} cannot be longer than {} characters",
+            field, max_chars
+        ));
+    }
+    Ok(())
+}
+
+/// Shape check for IANA zone names ("UTC", "America/Argentina/Buenos_Aires",
+/// "Etc/GMT+5"). The WASM has no tz database, so existence isn't checked.
+fn validate_timezone(timezone: &str) -> Result<(), String> {
+    let well_formed = !timezone.is_empty()
+        && timezone.len() <= 64
+        && timezone.split('/').all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+'))
+        });
+    if !well_formed {
+        return Err(format!("'{}' is not a valid IANA timezone name", timezone));
+    }
+    Ok(())
+}
+
+/// Set, replace or clear (`Some("")`) an optional profile register in place,
+/// keeping the other fields' registers untouched. `None` leaves it as is.
+fn apply_profile_field(register: &mut Option<LwwRegister<String>>, value: Option<String>) {
+    match (register.as_mut(), value) {
+        (_, None) => {}
+        (Some(existing), Some(v)) => existing.set(v),
+        (None, Some(v)) => *register = Some(LwwRegister::new(v)),
+    }
+}
+
+/// Derived from a member's last heartbeat; see `get_presence`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "calimero_sdk::serde")]
+pub enum PresenceStatus {
+    Online,
+    Away,
+    Offline,
+}
+
+/// One row of `get_presence`. `last_active` (node time, nanoseconds) is
+/// hidden for members in invisible mode, except in their own row.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct MemberPresence {
+    pub identity: UserId,
+    pub username: Option<String>,
+    pub status: PresenceStatus,
+    pub last_active: Option<u64>,
+    pub invisible: bool,
+}
+
+/// Roster entry, written by the member themselves via `register_member`
+/// and `leave`. `left_at` is `None` while the member is present; re-joining
+/// resets it.
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "calimero_sdk::borsh")]
+pub struct StoredMember {
+    pub joined_at: LwwRegister<u64>,
+    /// Self-reported by the joining member. `register_member` only checks
+    /// that it names someone else the context already knows; nothing proves
+    /// that member actually sent the invite.
+    pub invited_by: LwwRegister<Option<UserId>>,
+    pub left_at: LwwRegister<Option<u64>>,
+}
+
+impl MergeableTrait for StoredMember {
+    fn merge(&mut self, other: &Self) -> Result<(), MergeError> {
+        MergeableTrait::merge(&mut self.joined_at, &other.joined_at)?;
+        MergeableTrait::merge(&mut self.invited_by, &other.invited_by)?;
+        MergeableTrait::merge(&mut self.left_at, &other.left_at)?;
         Ok(())
     }
 }
 
+/// Optional filters for `get_members`. Omitted fields don't filter.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct MemberFilter {
+    pub role: Option<Role>,
+    pub presence: Option<PresenceStatus>,
+    /// Include members who have left. Defaults to `false`.
+    pub include_left: Option<bool>,
+}
+
+/// One row of `get_members`: roster, role, presence and unread state joined
+/// together. `joined_at` is `None` for members inferred from a profile who
+/// never called `register_member` (they joined before the roster existed).
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct MemberInfo {
+    pub identity: UserId,
+    pub username: Option<String>,
+    pub role: Role,
+    pub presence: PresenceStatus,
+    pub last_active: Option<u64>,
+    pub joined_at: Option<u64>,
+    pub invited_by: Option<UserId>,
+    pub left_at: Option<u64>,
+    /// The caller's own unread counts; `None` on every other row, since
+    /// they would reveal other members' read positions.
+    pub unread_count: Option<u32>,
+    pub unread_mentions: Option<u32>,
+}
+
+/// A member who has read a given message; see `get_seen_by`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct SeenBy {
+    pub identity: UserId,
+    pub username: Option<String>,
+}
+
+/// Delivery state of the caller's latest DM message, from the other
+/// member's point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "calimero_sdk::serde")]
+pub enum DeliveryStatus {
+    /// Not yet seen online since it was sent.
+    Sent,
+    /// The recipient has been active since it was sent.
+    Delivered,
+    /// The recipient's read position covers it.
+    Read,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "calimero_sdk::serde")]
+pub struct DmStatus {
+    pub message_id: MessageId,
+    pub status: DeliveryStatus,
+}
+
+/// `get_seen_by` is meant for small conversations; above this many members
+/// it refuses rather than returning an unwieldy list.
+const SEEN_BY_MAX_MEMBERS: usize = 50;
+
+/// Message timestamps are client-supplied seconds, heartbeats are node
+/// nanoseconds. Receipts only report `Read` when enabled; otherwise the
+/// best the sender sees is `Delivered`.
+fn delivery_status(
+    sent_at_secs: u64,
+    recipient_last_active: Option<u64>,
+    recipient_last_read: u64,
+    receipts_enabled: bool,
+) -> DeliveryStatus {
+    if receipts_enabled && recipient_last_read >= sent_at_secs {
+        return DeliveryStatus::Read;
+    }
+    match recipient_last_active {
+        Some(last) if last / NANOS_PER_SEC >= sent_at_secs => DeliveryStatus::Delivered,
+        _ => DeliveryStatus::Sent,
+    }
+}
+
+/// Default window after a heartbeat in which a member counts as online.
+const PRESENCE_ONLINE_WITHIN_SECS: u64 = 2 * 60;
+/// Default window after which a member stops counting as away.
+const PRESENCE_AWAY_WITHIN_SECS: u64 = 15 * 60;
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Minimum gap between two `Event::Typing` emissions from the same user.
+const TYPING_MIN_INTERVAL_SECS: u64 = 3;
+
+fn presence_status(
+    last_active: Option<u64>,
+    now: u64,
+    online_within_secs: u64,
+    away_within_secs: u64,
+) -> PresenceStatus {
+    let Some(last) = last_active else {
+        return PresenceStatus::Offline;
+    };
+    let idle = now.saturating_sub(last);
+    if idle <= online_within_secs.saturating_mul(NANOS_PER_SEC) {
+        PresenceStatus::Online
+    } else if idle <= away_within_secs.saturating_mul(NANOS_PER_SEC) {
+        PresenceStatus::Away
+    } else {
+        PresenceStatus::Offline
+    }
+}
+
 /// One context = one conversation (channel or DM).
 /// Messages, threads, reactions, profiles, and metadata live here.
 #[app::state(emits = Event)]
@@ -444,6 +1756,9 @@ pub struct MeroChat {
     threads: UnorderedMap<MessageId, AuthoredVector<Message>>,
     reactions: UnorderedMap<MessageId, UnorderedMap<String, UnorderedSet<String>>>,
     profiles: AuthoredMap<UserId, StoredProfile>,
+    /// Display name, bio, pronouns, timezone and status. Each member writes
+    /// only their own entry.
+    profile_details: AuthoredMap<UserId, ProfileDetails>,
     /// Per-context moderation roles. Missing entry = Role::User (default).
     /// LwwRegister-wrapped so the storage layer has merge semantics.
     roles: UnorderedMap<UserId, LwwRegister<Role>>,
@@ -454,6 +1769,135 @@ pub struct MeroChat {
     /// Written by delete_message regardless of AuthoredVector ownership, so
     /// admins/mods can delete messages they didn't author.
     deleted_messages: UnorderedSet<String>,
+    /// Thread root ID → everyone who authored the root or replied to it.
+    thread_participants: UnorderedMap<MessageId, UnorderedSet<UserId>>,
+    /// Explicit follow/unfollow choices, keyed by user then thread root.
+    /// Missing entry = following iff the user is a participant.
+    thread_follows: UnorderedMap<UserId, UnorderedMap<MessageId, LwwRegister<bool>>>,
+    /// Per-user, per-thread last-read timestamp. Same semantics as
+    /// `read_receipts`, scoped to the replies under one thread root.
+    thread_read_receipts: UnorderedMap<UserId, UnorderedMap<MessageId, LwwRegister<u64>>>,
+    /// Thread root ID → state. Missing entry = ThreadState::Open (default).
+    thread_states: UnorderedMap<MessageId, LwwRegister<ThreadState>>,
+    /// Thread reply ID → thread root, for replies sent with
+    /// `also_send_to_channel`. The top-level entry with the same ID is a
+    /// reference to the reply in `threads`. Kept out of `Message` so stored
+    /// messages keep their Borsh layout.
+    broadcast_roots: UnorderedMap<MessageId, LwwRegister<MessageId>>,
+    /// Message ID → the message it replies to inline (`send_message`'s
+    /// `reply_to`). A side table for the same reason as `broadcast_roots`.
+    reply_targets: UnorderedMap<MessageId, LwwRegister<MessageId>>,
+    /// Message ID → where it was forwarded from, for `forward_message`.
+    forward_origins: UnorderedMap<MessageId, LwwRegister<ForwardProvenance>>,
+    /// Message ID → hashtags and channel references parsed from its text.
+    message_tags: UnorderedMap<MessageId, LwwRegister<MessageTags>>,
+    /// Per-user username history, appended by `change_username`.
+    username_history: UnorderedMap<UserId, Vector<UsernameChange>>,
+    /// `normalize_username(name)` → owner. Rejects duplicates at write time.
+    /// Concurrent claims on different nodes can still both land in
+    /// `profiles`; the lowest `UserId` then keeps the name for mentions, and
+    /// `get_profiles` flags every other holder.
+    username_index: UnorderedMap<String, LwwRegister<UserId>>,
+    /// Per-user last heartbeat (node time, nanoseconds). Silent writes only.
+    last_active: UnorderedMap<UserId, LwwRegister<u64>>,
+    /// Members who opted into invisible mode. Missing entry = visible.
+    invisible: UnorderedMap<UserId, LwwRegister<bool>>,
+    /// Thresholds `get_presence` uses to derive online/away/offline.
+    presence_online_within_secs: LwwRegister<u64>,
+    presence_away_within_secs: LwwRegister<u64>,
+    /// Per-user time of the last `Event::Typing` (node time, nanoseconds).
+    /// One overwritten register per member — it exists for rate limiting
+    /// and so the event rides a delta, not to keep typing history.
+    typing_last_emitted: UnorderedMap<UserId, LwwRegister<u64>>,
+    /// Member roster. Each member writes only their own entry.
+    members: AuthoredMap<UserId, StoredMember>,
+    /// When `false`, `get_seen_by` is unavailable and DM status stops at
+    /// `Delivered`. Read positions are still stored for unread counts.
+    read_receipts_enabled: LwwRegister<bool>,
+    notification_settings: AuthoredMap<UserId, StoredNotificationSettings>,
+    broadcast_mention_policy: LwwRegister<BroadcastMentionPolicy>,
+    attachment_policy: LwwRegister<AttachmentPolicy>,
+    /// Previous versions of edited messages, oldest first.
+    edit_history: UnorderedMap<MessageId, Vector<MessageEdit>>,
+    /// Message ID → media metadata of its attachments. A side table rather
+    /// than a field on `Attachment`, so attachments stored before it existed
+    /// still decode.
+    attachment_media: UnorderedMap<MessageId, LwwRegister<MessageMedia>>,
+}
+
+/// The root state as it was before threads, presence, the member roster and
+/// the per-message side tables were added. Only used by `migrate_v1_to_v2`.
+#[derive(BorshDeserialize)]
+#[borsh(crate = "calimero_sdk::borsh")]
+struct MeroChatV1 {
+    name: LwwRegister<String>,
+    context_type: LwwRegister<ContextType>,
+    description: LwwRegister<String>,
+    created_at: LwwRegister<u64>,
+    creator: LwwRegister<String>,
+    messages: AuthoredVector<Message>,
+    threads: UnorderedMap<MessageId, AuthoredVector<Message>>,
+    reactions: UnorderedMap<MessageId, UnorderedMap<String, UnorderedSet<String>>>,
+    profiles: AuthoredMap<UserId, StoredProfile>,
+    roles: UnorderedMap<UserId, LwwRegister<Role>>,
+    read_receipts: UnorderedMap<UserId, LwwRegister<u64>>,
+    deleted_messages: UnorderedSet<String>,
+}
+
+/// Upgrade a context created before the new state fields existed. The root
+/// is decoded strictly, so without this every call on such a context would
+/// fail once the app is upgraded.
+///
+/// The existing collections are carried over as they are; `Message` and
+/// `StoredProfile` kept their Borsh layout, so their entries still decode.
+/// Everything added since starts out the way `init` leaves it, except that
+/// nothing is seeded: the member roster and `username_index` stay empty and
+/// fall back to scanning `profiles`, and the remaining side tables only
+/// describe messages sent after the upgrade.
+#[app::migrate]
+pub fn migrate_v1_to_v2() -> MeroChat {
+    let bytes = calimero_sdk::read_raw()
+        .unwrap_or_else(|| env::panic_str("Migration found no existing state"));
+    let old = MeroChatV1::try_from_slice(&bytes)
+        .unwrap_or_else(|e| env::panic_str(&format!("Failed to decode old state: {}", e)));
+
+    MeroChat {
+        name: old.name,
+        context_type: old.context_type,
+        description: old.description,
+        created_at: old.created_at,
+        creator: old.creator,
+        messages: old.messages,
+        threads: old.threads,
+        reactions: old.reactions,
+        profiles: old.profiles,
+        profile_details: AuthoredMap::new(),
+        roles: old.roles,
+        read_receipts: old.read_receipts,
+        deleted_messages: old.deleted_messages,
+        thread_participants: UnorderedMap::new(),
+        thread_follows: UnorderedMap::new(),
+        thread_read_receipts: UnorderedMap::new(),
+        thread_states: UnorderedMap::new(),
+        broadcast_roots: UnorderedMap::new(),
+        reply_targets: UnorderedMap::new(),
+        forward_origins: UnorderedMap::new(),
+        message_tags: UnorderedMap::new(),
+        username_history: UnorderedMap::new(),
+        username_index: UnorderedMap::new(),
+        last_active: UnorderedMap::new(),
+        invisible: UnorderedMap::new(),
+        presence_online_within_secs: LwwRegister::new(PRESENCE_ONLINE_WITHIN_SECS),
+        presence_away_within_secs: LwwRegister::new(PRESENCE_AWAY_WITHIN_SECS),
+        typing_last_emitted: UnorderedMap::new(),
+        members: AuthoredMap::new(),
+        read_receipts_enabled: LwwRegister::new(true),
+        notification_settings: AuthoredMap::new(),
+        broadcast_mention_policy: LwwRegister::new(BroadcastMentionPolicy::default()),
+        attachment_policy: LwwRegister::new(AttachmentPolicy::default()),
+        edit_history: UnorderedMap::new(),
+        attachment_media: UnorderedMap::new(),
+    }
 }
 
 #[app::logic]
@@ -473,17 +1917,31 @@ impl MeroChat {
         let mut roles = UnorderedMap::new();
         let _ = roles.insert(UserId::new(env::executor_id()), LwwRegister::new(Role::Admin));
 
+        let mut members = AuthoredMap::new();
+        let _ = members.insert(
+            UserId::new(env::executor_id()),
+            StoredMember {
+                joined_at: LwwRegister::new(env::time_now()),
+                invited_by: LwwRegister::new(None),
+                left_at: LwwRegister::new(None),
+            },
+        );
+
         // Pre-seed the creator's profile so get_profiles returns their name
         // immediately after context state gossip, without waiting for an
         // explicit set_profile call from the creator.
         let mut profiles = AuthoredMap::new();
-        if !creator_username.trim().is_empty() {
+        let mut username_index = UnorderedMap::new();
+        // An empty or invalid name (e.g. a reserved one) isn't seeded; the
+        // creator picks one through `set_profile` like everyone else.
+        if validate_username(&creator_username).is_ok() {
+            let _ = username_index.insert(
+                normalize_username(&creator_username),
+                LwwRegister::new(UserId::new(env::executor_id())),
+            );
             let _ = profiles.insert(
                 UserId::new(env::executor_id()),
-                StoredProfile {
-                    username: LwwRegister::new(creator_username),
-                    avatar: None,
-                },
+                StoredProfile::new(creator_username),
             );
         }
 
@@ -497,9 +1955,32 @@ impl MeroChat {
             threads: UnorderedMap::new(),
             reactions: UnorderedMap::new(),
             profiles,
+            profile_details: AuthoredMap::new(),
             roles,
             read_receipts: UnorderedMap::new(),
             deleted_messages: UnorderedSet::new(),
+            thread_participants: UnorderedMap::new(),
+            thread_follows: UnorderedMap::new(),
+            thread_read_receipts: UnorderedMap::new(),
+            thread_states: UnorderedMap::new(),
+            broadcast_roots: UnorderedMap::new(),
+            reply_targets: UnorderedMap::new(),
+            forward_origins: UnorderedMap::new(),
+            message_tags: UnorderedMap::new(),
+            username_history: UnorderedMap::new(),
+            username_index,
+            last_active: UnorderedMap::new(),
+            invisible: UnorderedMap::new(),
+            presence_online_within_secs: LwwRegister::new(PRESENCE_ONLINE_WITHIN_SECS),
+            presence_away_within_secs: LwwRegister::new(PRESENCE_AWAY_WITHIN_SECS),
+            typing_last_emitted: UnorderedMap::new(),
+            members,
+            read_receipts_enabled: LwwRegister::new(true),
+            notification_settings: AuthoredMap::new(),
+            broadcast_mention_policy: LwwRegister::new(BroadcastMentionPolicy::default()),
+            attachment_policy: LwwRegister::new(AttachmentPolicy::default()),
+            edit_history: UnorderedMap::new(),
+            attachment_media: UnorderedMap::new(),
         }
     }
 
@@ -510,6 +1991,8 @@ impl MeroChat {
             description: self.description.get().clone(),
             created_at: *self.created_at,
             creator: self.creator.get().clone(),
+            read_receipts_enabled: *self.read_receipts_enabled,
+            broadcast_mention_policy: *self.broadcast_mention_policy,
         }
     }
 
@@ -519,14 +2002,19 @@ impl MeroChat {
         self.get_info()
     }
 
-    /// No-op stub so frontends that call `mark_messages_as_read` don't get
-    /// a "method not found" error. Read state is tracked client-side only.
+    /// Legacy entry point for `mark_as_read`, kept for frontends that still
+    /// call `mark_messages_as_read`. The `channel` argument is ignored; a
+    /// missing `timestamp` marks everything up to the newest message as read.
     pub fn mark_messages_as_read(
-        &self,
+        &mut self,
         _channel: Option<String>,
-        _timestamp: Option<u64>,
+        timestamp: Option<u64>,
     ) -> app::Result<String, String> {
-        Ok("ok".to_string())
+        let timestamp = match timestamp {
+            Some(timestamp) => timestamp,
+            None => self.latest_message_timestamp(),
+        };
+        self.mark_as_read(timestamp)
     }
 
     /// Persist the caller's last-read position. No event is emitted so this
@@ -538,170 +2026,1278 @@ impl MeroChat {
         Ok("ok".to_string())
     }
 
-    /// Count messages newer than the caller's last-read timestamp, excluding
-    /// messages sent by the caller and soft-deleted messages.
-    pub fn get_unread_count(&self) -> u32 {
+    /// Mark everything up to and including `message_id` as read. A channel
+    /// message advances the context read position; a thread reply advances
+    /// that thread's position. Never moves a position backwards — use
+    /// `mark_unread_from` for that. Silent write, same as `mark_as_read`.
+    pub fn mark_message_as_read(&mut self, message_id: MessageId) -> app::Result<String, String> {
         let caller = Self::executor_id();
-        let last_read = self
-            .read_receipts
-            .get(&caller)
-            .ok()
-            .flatten()
-            .map(|r| *r.get())
-            .unwrap_or(0);
+        let (parent_id, message) = self
+            .locate_message(&message_id)
+            .ok_or_else(|| "Message not found".to_string())?;
+        let timestamp = *message.timestamp.get();
 
-        let mut count = 0u32;
-        if let Ok(iter) = self.messages.iter() {
-            for msg in iter {
-                if *msg.timestamp <= last_read {
-                    continue;
-                }
-                if msg.sender == caller {
-                    continue;
+        match parent_id {
+            Some(parent_id) => {
+                if self.thread_last_read(&caller, &parent_id).unwrap_or(0) < timestamp {
+                    self.set_thread_last_read(caller, &parent_id, timestamp);
                 }
-                let deleted = msg.deleted.as_ref().map(|r| **r).unwrap_or(false)
-                    || self.deleted_messages.contains(msg.id.get()).unwrap_or(false);
-                if deleted {
-                    continue;
+            }
+            None => {
+                if self.last_read(&caller) < timestamp {
+                    let _ = self
+                        .read_receipts
+                        .insert(caller, LwwRegister::new(timestamp));
                 }
-                count += 1;
             }
         }
-        count
+        Ok("ok".to_string())
+    }
+
+    /// Move the caller's read position to just before `message_id`, so it
+    /// and everything after it count as unread again. A thread reply moves
+    /// that thread's position instead. The write replaces the register with
+    /// a fresh one, so the lower position wins under LWW like any other
+    /// newer write. Positions are second-granular: other messages sharing
+    /// the target's timestamp become unread too.
+    pub fn mark_unread_from(&mut self, message_id: MessageId) -> app::Result<String, String> {
+        let caller = Self::executor_id();
+        let (parent_id, message) = self
+            .locate_message(&message_id)
+            .ok_or_else(|| "Message not found".to_string())?;
+        let position = message.timestamp.get().saturating_sub(1);
+
+        match parent_id {
+            Some(parent_id) => self.set_thread_last_read(caller, &parent_id, position),
+            None => {
+                let _ = self
+                    .read_receipts
+                    .insert(caller, LwwRegister::new(position));
+            }
+        }
+        Ok("ok".to_string())
+    }
+
+    /// Turn read receipts on or off for this context. Admins only in
+    /// channels; either member may change it in a DM.
+    pub fn set_read_receipts_enabled(&mut self, enabled: bool) -> app::Result<String, String> {
+        self.require_not_banned()?;
+        if *self.context_type.get() != ContextType::Dm
+            && self.role_of(&Self::executor_id()) != Role::Admin
+        {
+            return Err("Only admins can change read receipts".to_string());
+        }
+        self.read_receipts_enabled.set(enabled);
+        app::emit!(Event::InfoUpdated());
+        Ok("Read receipts updated".to_string())
+    }
+
+    /// Members whose read position covers `message_id`, derived from
+    /// `read_receipts` (or the thread's receipts for a reply). The sender is
+    /// never listed.
+    pub fn get_seen_by(&self, message_id: MessageId) -> app::Result<Vec<SeenBy>, String> {
+        if !*self.read_receipts_enabled {
+            return Err("Read receipts are disabled in this context".to_string());
+        }
+        let members = self.known_members();
+        if members.len() > SEEN_BY_MAX_MEMBERS {
+            return Err("Seen-by is only available in small conversations".to_string());
+        }
+        let (parent_id, message) = self
+            .locate_message(&message_id)
+            .ok_or_else(|| "Message not found".to_string())?;
+        let timestamp = *message.timestamp.get();
+
+        let mut seen = Vec::new();
+        for identity in members {
+            if identity == message.sender {
+                continue;
+            }
+            let last_read = match parent_id {
+                Some(ref parent_id) => self.thread_last_read(&identity, parent_id).unwrap_or(0),
+                None => self.last_read(&identity),
+            };
+            if last_read < timestamp {
+                continue;
+            }
+            seen.push(SeenBy {
+                identity,
+                username: match self.profiles.get(&identity) {
+                    Ok(Some(profile)) => Some(profile.username.get().clone()),
+                    _ => None,
+                },
+            });
+        }
+        Ok(seen)
+    }
+
+    /// Delivery status of the caller's most recent message in a DM. `None`
+    /// outside DMs or when the caller hasn't sent anything yet.
+    pub fn get_dm_status(&self) -> Option<DmStatus> {
+        if *self.context_type.get() != ContextType::Dm {
+            return None;
+        }
+        let caller = Self::executor_id();
+        let latest = self
+            .messages
+            .iter()
+            .ok()?
+            .filter(|msg| msg.sender == caller && !self.is_deleted(msg))
+            .max_by_key(|msg| *msg.timestamp.get())?;
+        let recipient = self.known_members().into_iter().find(|id| *id != caller)?;
+
+        let presence = self.presence_of(&recipient, &caller, env::time_now());
+        Some(DmStatus {
+            message_id: latest.id.get().clone(),
+            status: delivery_status(
+                *latest.timestamp.get(),
+                presence.last_active,
+                self.last_read(&recipient),
+                *self.read_receipts_enabled,
+            ),
+        })
+    }
+
+    /// Count unread channel messages plus unread replies in followed
+    /// threads, excluding the caller's own and soft-deleted messages.
+    pub fn get_unread_count(&self) -> u32 {
+        self.unread_count_for(&Self::executor_id())
     }
 
-    /// Count unread messages that mention the caller directly (@username),
-    /// or use a broadcast mention (@everyone / @here).
+    /// Count unread messages, channel or thread, that mention the caller
+    /// directly (@username) or use a broadcast mention (@everyone / @here).
     pub fn get_unread_mentions(&self) -> u32 {
+        self.unread_mentions_for(&Self::executor_id())
+    }
+
+    /// Count unread messages, channel or thread, whose text matches one of
+    /// the caller's keyword alerts (see `set_my_notification_settings`).
+    /// The caller's own messages never match.
+    pub fn get_unread_alerts(&self) -> u32 {
+        self.unread_summary_for(&Self::executor_id()).alert_count
+    }
+
+    /// Everything a context badge needs in one call: channel and followed
+    /// thread unread counts, mentions anywhere, and where to jump to.
+    pub fn get_unread_summary(&self) -> UnreadSummary {
+        self.unread_summary_for(&Self::executor_id())
+    }
+
+    pub fn get_my_notification_settings(&self) -> NotificationSettings {
+        self.notification_settings_of(&Self::executor_id())
+    }
+
+    /// Replace the caller's notification settings. Silent write, like
+    /// `mark_as_read`; the unread queries pick the change up immediately.
+    pub fn set_my_notification_settings(
+        &mut self,
+        settings: NotificationSettings,
+    ) -> app::Result<String, String> {
+        let keywords = normalize_keywords(settings.keywords)?;
         let caller = Self::executor_id();
-        let last_read = self
-            .read_receipts
-            .get(&caller)
+
+        match self.notification_settings.get(&caller) {
+            Ok(Some(mut stored)) => {
+                stored.level.set(settings.level);
+                stored.muted_until.set(settings.muted_until);
+                stored
+                    .thread_notifications
+                    .set(settings.thread_notifications);
+                stored.keywords.set(keywords);
+                let _ = self.notification_settings.update(&caller, stored);
+            }
+            _ => {
+                let stored = StoredNotificationSettings {
+                    level: LwwRegister::new(settings.level),
+                    muted_until: LwwRegister::new(settings.muted_until),
+                    thread_notifications: LwwRegister::new(settings.thread_notifications),
+                    keywords: LwwRegister::new(keywords),
+                };
+                let _ = self.notification_settings.insert(caller, stored);
+            }
+        }
+        Ok("Notification settings updated".to_string())
+    }
+
+    fn notification_settings_of(&self, user: &UserId) -> NotificationSettings {
+        match self.notification_settings.get(user) {
+            Ok(Some(stored)) => NotificationSettings {
+                level: *stored.level.get(),
+                muted_until: *stored.muted_until.get(),
+                thread_notifications: *stored.thread_notifications.get(),
+                keywords: stored.keywords.get().clone(),
+            },
+            _ => NotificationSettings::default(),
+        }
+    }
+
+    /// The user's context-level read position; `0` if they never marked.
+    fn last_read(&self, user: &UserId) -> u64 {
+        self.read_receipts
+            .get(user)
             .ok()
             .flatten()
             .map(|r| *r.get())
-            .unwrap_or(0);
+            .unwrap_or(0)
+    }
+
+    fn latest_message_timestamp(&self) -> u64 {
+        self.messages
+            .iter()
+            .ok()
+            .and_then(|iter| iter.map(|msg| *msg.timestamp.get()).max())
+            .unwrap_or(0)
+    }
+
+    fn unread_count_for(&self, user: &UserId) -> u32 {
+        let summary = self.unread_summary_for(user);
+        summary.channel_count + summary.thread_count
+    }
 
-        let mut count = 0u32;
+    fn unread_mentions_for(&self, user: &UserId) -> u32 {
+        self.unread_summary_for(user).mention_count
+    }
+
+    /// One pass over the channel and every thread. Channel messages count
+    /// against `read_receipts`, replies against the thread's own position, or
+    /// the channel position for a thread the user has never opened.
+    /// Replies only add to `thread_count` in threads the user follows, but a
+    /// mention or keyword alert counts wherever it is. A reply also sent to
+    /// the channel is counted once, as a channel message. The user's
+    /// notification settings are applied last.
+    fn unread_summary_for(&self, user: &UserId) -> UnreadSummary {
+        let last_read = self.last_read(user);
+        let settings = self.notification_settings_of(user);
+        let mut summary = UnreadSummary::default();
+        let mut oldest: Option<(u64, MessageId)> = None;
+        let mut note_oldest = |timestamp: u64, id: &MessageId| {
+            if oldest.as_ref().is_none_or(|(ts, _)| timestamp < *ts) {
+                oldest = Some((timestamp, id.clone()));
+            }
+        };
+
+        let mut channel_ids: HashSet<MessageId> = HashSet::new();
         if let Ok(iter) = self.messages.iter() {
             for msg in iter {
-                if *msg.timestamp <= last_read {
+                if *msg.timestamp <= last_read || msg.sender == *user || self.is_deleted(&msg) {
                     continue;
                 }
-                if msg.sender == caller {
-                    continue;
+                summary.channel_count += 1;
+                if self.mentions_user(&msg, user) {
+                    summary.mention_count += 1;
                 }
-                let deleted = msg.deleted.as_ref().map(|r| **r).unwrap_or(false)
-                    || self.deleted_messages.contains(msg.id.get()).unwrap_or(false);
-                if deleted {
-                    continue;
+                if !settings.keywords.is_empty() {
+                    // A broadcast reference has no text of its own; match the
+                    // thread reply it stands for.
+                    let text = match self.broadcast_root_of(msg.id.get()) {
+                        Some(root) => self
+                            .find_thread_reply(&root, msg.id.get())
+                            .map(|reply| reply.text.get().clone())
+                            .unwrap_or_default(),
+                        None => msg.text.get().clone(),
+                    };
+                    if text_matches_keywords(&text, &settings.keywords) {
+                        summary.alert_count += 1;
+                    }
                 }
+                note_oldest(*msg.timestamp, msg.id.get());
+                channel_ids.insert(msg.id.get().clone());
+            }
+        }
 
-                // Broadcast mentions (@everyone / @here) always count.
-                let is_broadcast = if let Ok(mut unames) = msg.mentions_usernames.iter() {
-                    unames.any(|r| {
-                        let s = r.get().as_str();
-                        s == "everyone" || s == "here"
-                    })
-                } else {
-                    false
+        if let Ok(entries) = self.threads.entries() {
+            for (parent_id, thread) in entries {
+                let thread_read = self.thread_last_read(user, &parent_id).unwrap_or(last_read);
+                let participants: Vec<UserId> = match self.thread_participants.get(&parent_id) {
+                    Ok(Some(set)) => set.iter().map(|iter| iter.collect()).unwrap_or_default(),
+                    _ => Vec::new(),
                 };
+                let following = self.is_following_thread(user, &parent_id, &participants);
+
+                let Ok(iter) = thread.iter() else { continue };
+                for reply in iter {
+                    if *reply.timestamp <= thread_read
+                        || reply.sender == *user
+                        || channel_ids.contains(reply.id.get())
+                        || self.is_deleted(&reply)
+                    {
+                        continue;
+                    }
+                    let mentioned = self.mentions_user(&reply, user);
+                    let alerted = text_matches_keywords(reply.text.get(), &settings.keywords);
+                    if following {
+                        summary.thread_count += 1;
+                    }
+                    if mentioned {
+                        summary.mention_count += 1;
+                    }
+                    if alerted {
+                        summary.alert_count += 1;
+                    }
+                    if following || mentioned || alerted {
+                        note_oldest(*reply.timestamp, reply.id.get());
+                    }
+                }
+            }
+        }
 
-                if is_broadcast {
-                    count += 1;
-                    continue;
+        summary.oldest_unread_id = oldest.map(|(_, id)| id);
+        apply_notification_settings(summary, &settings, env::time_now() / NANOS_PER_SEC)
+    }
+
+    /// Direct mention of `user`, or a broadcast mention (@everyone / @here).
+    fn mentions_user(&self, msg: &Message, user: &UserId) -> bool {
+        let is_broadcast = if let Ok(mut unames) = msg.mentions_usernames.iter() {
+            unames.any(|r| BROADCAST_MENTIONS.contains(&r.get().as_str()))
+        } else {
+            false
+        };
+        if is_broadcast {
+            return true;
+        }
+        if let Ok(mut mentions) = msg.mentions.iter() {
+            return mentions.any(|uid| uid == *user);
+        }
+        false
+    }
+
+    // ── Threads: participants, follows, per-thread read position ───────────
+
+    /// Follow a thread so it shows up in `get_followed_threads_with_unread`.
+    /// Silent write, like `mark_as_read` — only the caller's view changes.
+    pub fn follow_thread(&mut self, parent_id: MessageId) -> app::Result<String, String> {
+        self.require_not_banned()?;
+        if !self.threads.contains(&parent_id).unwrap_or(false)
+            && self.find_message(&parent_id).is_none()
+        {
+            return Err("Thread not found".to_string());
+        }
+
+        let caller = Self::executor_id();
+        self.set_thread_follow(caller, &parent_id, true);
+
+        // Start the read position at the latest reply so following an old
+        // thread doesn't flood the inbox with its entire history.
+        if self.thread_last_read(&caller, &parent_id).is_none() {
+            let (_, last_ts) = self.get_thread_info(&parent_id);
+            self.set_thread_last_read(caller, &parent_id, last_ts);
+        }
+        Ok("ok".to_string())
+    }
+
+    /// Stop following a thread. Overrides the implicit follow that
+    /// participants get, so replying later does not re-follow on its own.
+    pub fn unfollow_thread(&mut self, parent_id: MessageId) -> app::Result<String, String> {
+        self.require_not_banned()?;
+        let caller = Self::executor_id();
+        self.set_thread_follow(caller, &parent_id, false);
+        Ok("ok".to_string())
+    }
+
+    /// Persist the caller's last-read position inside one thread. Silent
+    /// write, same as `mark_as_read`.
+    pub fn mark_thread_as_read(
+        &mut self,
+        parent_id: MessageId,
+        timestamp: u64,
+    ) -> app::Result<String, String> {
+        let caller = Self::executor_id();
+        self.set_thread_last_read(caller, &parent_id, timestamp);
+        Ok("ok".to_string())
+    }
+
+    pub fn get_thread_summary(&self, parent_id: MessageId) -> ThreadSummary {
+        self.thread_summary(&Self::executor_id(), &parent_id)
+    }
+
+    /// Threads the caller follows (explicitly, or implicitly by participating),
+    /// most recently active first — the data behind a "Threads" inbox.
+    pub fn get_followed_threads_with_unread(&self) -> Vec<ThreadSummary> {
+        let caller = Self::executor_id();
+
+        let mut candidates: Vec<MessageId> = Vec::new();
+        if let Ok(entries) = self.thread_participants.entries() {
+            for (parent_id, participants) in entries {
+                if participants.contains(&caller).unwrap_or(false) {
+                    candidates.push(parent_id);
+                }
+            }
+        }
+        if let Ok(Some(follows)) = self.thread_follows.get(&caller) {
+            if let Ok(entries) = follows.entries() {
+                for (parent_id, _) in entries {
+                    if !candidates.contains(&parent_id) {
+                        candidates.push(parent_id);
+                    }
+                }
+            }
+        }
+
+        let mut result: Vec<ThreadSummary> = candidates
+            .iter()
+            .map(|parent_id| self.thread_summary(&caller, parent_id))
+            .filter(|summary| summary.following)
+            .collect();
+        result.sort_by_key(|summary| std::cmp::Reverse(summary.last_reply_timestamp));
+        result
+    }
+
+    /// Change a thread's state. Authorisation:
+    /// - `Admin`/`Mod` may set any state, including locking and unlocking.
+    /// - The thread root's author may toggle Open ↔ Resolved while unlocked.
+    pub fn set_thread_state(
+        &mut self,
+        parent_id: MessageId,
+        state: ThreadState,
+    ) -> app::Result<String, String> {
+        self.require_not_banned()?;
+        let me = Self::executor_id();
+        let root = self
+            .find_message(&parent_id)
+            .ok_or_else(|| "Message not found".to_string())?;
+
+        let current = self.thread_state_of(&parent_id);
+        if !Self::can_set_thread_state(self.role_of(&me), root.sender == me, current, state) {
+            return Err("You don't have permission to change this thread's state".to_string());
+        }
+
+        // Open is the implicit default — same trick as `roles`.
+        if state == ThreadState::Open {
+            let _ = self.thread_states.remove(&parent_id);
+        } else {
+            let _ = self
+                .thread_states
+                .insert(parent_id.clone(), LwwRegister::new(state));
+        }
+
+        app::emit!(Event::ThreadStateUpdated(parent_id));
+        Ok("Thread state updated".to_string())
+    }
+
+    pub fn update_info(
+        &mut self,
+        name: Option<String>,
+        description: Option<String>,
+    ) -> app::Result<String, String> {
+        self.require_not_banned()?;
+        if let Some(n) = name {
+            self.name.set(n);
+        }
+        if let Some(d) = description {
+            self.description.set(d);
+        }
+        app::emit!(Event::InfoUpdated());
+        Ok("Info updated".to_string())
+    }
+
+    /// Set or update this user's profile in the current context.
+    ///
+    /// Username is **write-once**: once a profile exists for an identity,
+    /// the `username` field is preserved on subsequent calls — only the
+    /// avatar can be changed (`None` keeps the current one; use
+    /// `set_avatar(None)` to remove it). This freezes a member's handle to
+    /// whatever they registered at first profile creation (typically on
+    /// join), so other members keep seeing a stable identity even if the
+    /// user later rotates their local `chat-username` from a different
+    /// device.
+    ///
+    /// Caveat: enforcement is per-node-state at write time, not at the
+    /// CRDT merge layer. Two nodes that each see a freshly-empty profile
+    /// for the same identity and write different usernames concurrently
+    /// will still converge via `LwwRegister` semantics. In practice the
+    /// initial set happens on one device, so this is rare.
+    ///
+    /// Deliberate renames go through `change_username`.
+    pub fn set_profile(
+        &mut self,
+        username: String,
+        avatar: Option<AttachmentInput>,
+    ) -> app::Result<String, String> {
+        self.require_not_banned()?;
+        validate_username(&username)?;
+
+        let executor_id = Self::executor_id();
+        let avatar = match avatar {
+            Some(input) => Some(avatar_input_to_attachment(input, &env::context_id())?),
+            None => None,
+        };
+
+        // An existing profile keeps its frozen username; only the avatar is
+        // mutable.
+        if !self.profiles.contains(&executor_id).unwrap_or(false) {
+            self.claim_username(&executor_id, &username)?;
+            let _ = self
+                .profiles
+                .insert(executor_id, StoredProfile::new(username));
+        }
+        if let Some(a) = avatar {
+            self.edit_profile_details(executor_id, |details| Self::apply_avatar(details, Some(a)));
+        }
+
+        app::emit!(Event::ProfileUpdated(executor_id.to_string()));
+        Ok("Profile set".to_string())
+    }
+
+    /// Replace (`Some`) or remove (`None`) the caller's avatar.
+    pub fn set_avatar(&mut self, avatar: Option<AttachmentInput>) -> app::Result<String, String> {
+        self.require_not_banned()?;
+        let avatar = match avatar {
+            Some(input) => Some(avatar_input_to_attachment(input, &env::context_id())?),
+            None => None,
+        };
+
+        let executor_id = Self::executor_id();
+        if !self.profiles.contains(&executor_id).unwrap_or(false) {
+            return Err("Set a profile before setting an avatar".to_string());
+        }
+        self.edit_profile_details(executor_id, |details| Self::apply_avatar(details, avatar));
+
+        app::emit!(Event::ProfileUpdated(executor_id.to_string()));
+        Ok("Avatar updated".to_string())
+    }
+
+    fn apply_avatar(details: &mut ProfileDetails, avatar: Option<Attachment>) {
+        match details.avatar.as_mut() {
+            Some(register) => register.set(avatar),
+            None => details.avatar = Some(LwwRegister::new(avatar)),
+        }
+    }
+
+    /// Rename the caller. At most once per `USERNAME_CHANGE_COOLDOWN_NS`;
+    /// the old name is kept in the username history. Messages keep their
+    /// `sender_username` snapshot and mentions resolve through `UserId`, so
+    /// nothing already sent needs rewriting.
+    pub fn change_username(&mut self, new_username: String) -> app::Result<String, String> {
+        self.require_not_banned()?;
+        validate_username(&new_username)?;
+
+        let executor_id = Self::executor_id();
+        let mut existing = match self.profiles.get(&executor_id) {
+            Ok(Some(profile)) => profile,
+            _ => return Err("Set a profile before changing your username".to_string()),
+        };
+        let current_username = existing.username.get().clone();
+        if current_username == new_username {
+            return Err("That is already your username".to_string());
+        }
+
+        let now = env::time_now();
+        let mut history = match self.username_history.get(&executor_id) {
+            Ok(Some(history)) => history,
+            _ => Vector::new(),
+        };
+        let last_change = match history.len() {
+            Ok(len) if len > 0 => history.get(len - 1).ok().flatten().map(|c| c.changed_at),
+            _ => None,
+        };
+        if let Some(last) = last_change {
+            if now.saturating_sub(last) < USERNAME_CHANGE_COOLDOWN_NS {
+                return Err("You can only change your username once every 7 days".to_string());
+            }
+        }
+
+        self.claim_username(&executor_id, &new_username)?;
+        // A case-only rename keeps the same key; releasing it would drop
+        // the claim just made.
+        if normalize_username(&current_username) != normalize_username(&new_username) {
+            self.release_username(&executor_id, &current_username);
+        }
+
+        let _ = history.push(UsernameChange {
+            previous_username: current_username,
+            changed_at: now,
+        });
+        let _ = self.username_history.insert(executor_id, history);
+
+        existing.username.set(new_username);
+        let _ = self.profiles.update(&executor_id, existing);
+
+        app::emit!(Event::ProfileUpdated(executor_id.to_string()));
+        Ok("Username changed".to_string())
+    }
+
+    /// Update the caller's optional profile fields. `None` leaves a field
+    /// unchanged and `Some("")` clears it; only the fields passed are
+    /// rewritten, so concurrent edits to other fields on other devices survive.
+    pub fn update_profile_details(
+        &mut self,
+        display_name: Option<String>,
+        bio: Option<String>,
+        pronouns: Option<String>,
+        timezone: Option<String>,
+    ) -> app::Result<String, String> {
+        self.require_not_banned()?;
+        if let Some(ref v) = display_name {
+            validate_field_length("Display name", v, DISPLAY_NAME_MAX_CHARS)?;
+        }
+        if let Some(ref v) = bio {
+            validate_field_length("Bio", v, BIO_MAX_CHARS)?;
+        }
+        if let Some(ref v) = pronouns {
+            validate_field_length("Pronouns", v, PRONOUNS_MAX_CHARS)?;
+        }
+        if let Some(ref v) = timezone {
+            if !v.is_empty() {
+                validate_timezone(v)?;
+            }
+        }
+
+        let executor_id = Self::executor_id();
+        if !self.profiles.contains(&executor_id).unwrap_or(false) {
+            return Err("Set a profile before editing profile details".to_string());
+        }
+        self.edit_profile_details(executor_id, |details| {
+            apply_profile_field(&mut details.display_name, display_name);
+            apply_profile_field(&mut details.bio, bio);
+            apply_profile_field(&mut details.pronouns, pronouns);
+            apply_profile_field(&mut details.timezone, timezone);
+        });
+
+        app::emit!(Event::ProfileUpdated(executor_id.to_string()));
+        Ok("Profile updated".to_string())
+    }
+
+    /// Set the caller's custom status. Pass empty `emoji` and `text` to clear.
+    pub fn set_status(
+        &mut self,
+        emoji: String,
+        text: String,
+        expires_at: Option<u64>,
+    ) -> app::Result<String, String> {
+        self.require_not_banned()?;
+        validate_field_length("Status emoji", &emoji, STATUS_EMOJI_MAX_CHARS)?;
+        validate_field_length("Status text", &text, STATUS_TEXT_MAX_CHARS)?;
+
+        let executor_id = Self::executor_id();
+        if !self.profiles.contains(&executor_id).unwrap_or(false) {
+            return Err("Set a profile before setting a status".to_string());
+        }
+        let status = CustomStatus {
+            emoji,
+            text,
+            expires_at,
+        };
+        self.edit_profile_details(executor_id, |details| match details.status.as_mut() {
+            Some(register) => register.set(status),
+            None => details.status = Some(LwwRegister::new(status)),
+        });
+
+        app::emit!(Event::ProfileUpdated(executor_id.to_string()));
+        Ok("Status set".to_string())
+    }
+
+    pub fn get_profile(&self, identity: UserId) -> Option<UserProfile> {
+        let profile = self.profiles.get(&identity).ok().flatten()?;
+        let mut result = self.to_user_profile(identity, &profile);
+
+        let key = normalize_username(&result.username);
+        if let Ok(mut entries) = self.profiles.entries() {
+            result.duplicate_username = entries.any(|(other_id, other)| {
+                other_id < identity && normalize_username(other.username.get()) == key
+            });
+        }
+        Some(result)
+    }
+
+    pub fn get_profiles(&self) -> Vec<UserProfile> {
+        let mut result = Vec::new();
+        if let Ok(entries) = self.profiles.entries() {
+            for (user_id, profile) in entries {
+                result.push(self.to_user_profile(user_id, &profile));
+            }
+        }
+
+        // Deterministic tie-break for concurrent duplicates: the lowest
+        // `UserId` keeps the plain name, every other holder is flagged.
+        let mut canonical: HashMap<String, UserId> = HashMap::new();
+        for profile in &result {
+            let entry = canonical
+                .entry(normalize_username(&profile.username))
+                .or_insert(profile.identity);
+            if profile.identity < *entry {
+                *entry = profile.identity;
+            }
+        }
+        for profile in result.iter_mut() {
+            profile.duplicate_username =
+                canonical.get(&normalize_username(&profile.username)) != Some(&profile.identity);
+        }
+        result
+    }
+
+    fn to_user_profile(&self, identity: UserId, profile: &StoredProfile) -> UserProfile {
+        // Cleared fields are stored as empty strings.
+        let field = |r: &Option<LwwRegister<String>>| {
+            r.as_ref()
+                .map(|r| r.get().clone())
+                .filter(|v| !v.is_empty())
+        };
+        let details = self
+            .profile_details
+            .get(&identity)
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        let now = env::time_now() / NANOS_PER_SEC;
+
+        UserProfile {
+            identity,
+            username: profile.username.get().clone(),
+            avatar: details
+                .avatar
+                .as_ref()
+                .and_then(|a| a.get().as_ref().map(Attachment::to_public)),
+            legacy_avatar: match details.avatar {
+                Some(_) => None,
+                None => profile.avatar.as_ref().map(|a| a.get().clone()),
+            },
+            display_name: field(&details.display_name),
+            bio: field(&details.bio),
+            pronouns: field(&details.pronouns),
+            timezone: field(&details.timezone),
+            status: details
+                .status
+                .as_ref()
+                .map(|r| r.get().clone())
+                .filter(|s| s.is_active(now)),
+            previous_usernames: self.previous_usernames_of(&identity),
+            duplicate_username: false,
+        }
+    }
+
+    /// Apply `edit` to the user's `ProfileDetails`, creating the entry on
+    /// first use.
+    fn edit_profile_details(&mut self, user: UserId, edit: impl FnOnce(&mut ProfileDetails)) {
+        match self.profile_details.get(&user) {
+            Ok(Some(mut details)) => {
+                edit(&mut details);
+                let _ = self.profile_details.update(&user, details);
+            }
+            _ => {
+                let mut details = ProfileDetails::default();
+                edit(&mut details);
+                let _ = self.profile_details.insert(user, details);
+            }
+        }
+    }
+
+    /// Reserve `username` for `user` in the uniqueness index. Re-claiming a
+    /// name the user already holds (e.g. a case-only rename) is allowed.
+    /// The current holder is decided by `user_by_username`.
+    fn claim_username(&mut self, user: &UserId, username: &str) -> Result<(), String> {
+        let key = normalize_username(username);
+        if self
+            .user_by_username(&key)
+            .is_some_and(|owner| owner != *user)
+        {
+            return Err(format!("Username '{}' is already taken", username.trim()));
+        }
+        let _ = self.username_index.insert(key, LwwRegister::new(*user));
+        Ok(())
+    }
+
+    fn release_username(&mut self, user: &UserId, username: &str) {
+        let key = normalize_username(username);
+        if let Ok(Some(owner)) = self.username_index.get(&key) {
+            if *owner.get() == *user {
+                let _ = self.username_index.remove(&key);
+            }
+        }
+    }
+
+    fn previous_usernames_of(&self, user: &UserId) -> Vec<String> {
+        match self.username_history.get(user) {
+            Ok(Some(history)) => {
+                if let Ok(iter) = history.iter() {
+                    iter.map(|c| c.previous_username).collect()
+                } else {
+                    Vec::new()
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // ── Attachment policy ──────────────────────────────────────────────────
+
+    pub fn get_attachment_policy(&self) -> AttachmentPolicy {
+        self.attachment_policy.get().clone()
+    }
+
+    /// Admin-only: replace the context's attachment policy. Existing
+    /// attachments are not re-checked.
+    pub fn set_attachment_policy(
+        &mut self,
+        policy: AttachmentPolicy,
+    ) -> app::Result<String, String> {
+        if self.role_of(&Self::executor_id()) != Role::Admin {
+            return Err("Only admins can change the attachment policy".to_string());
+        }
+        if policy.max_file_size == 0 || policy.max_attachments_per_message == 0 {
+            return Err("Attachment limits must be greater than zero".to_string());
+        }
+        self.attachment_policy.set(policy);
+        app::emit!(Event::InfoUpdated());
+        Ok("Attachment policy updated".to_string())
+    }
+
+    pub fn get_attachment_usage(&self) -> AttachmentUsage {
+        AttachmentUsage {
+            used_bytes: self.attachment_usage_of(&Self::executor_id()),
+            quota_bytes: self.attachment_policy.get().user_quota_bytes,
+        }
+    }
+
+    /// Every attachment in the context, channel and threads, newest first.
+    /// Deleted messages are skipped. `sender` and `mime_prefix` (e.g.
+    /// `"image/"` or `"application/pdf"`) narrow the results; `kind` picks
+    /// files or images, both when omitted.
+    pub fn get_attachments(
+        &self,
+        kind: Option<AttachmentKind>,
+        sender: Option<UserId>,
+        mime_prefix: Option<String>,
+        limit: Option<usize>,
+        cursor: Option<String>,
+    ) -> app::Result<AttachmentPage, String> {
+        let mime_prefix = mime_prefix.map(|prefix| prefix.to_ascii_lowercase());
+        let mut entries = Vec::new();
+
+        let mut collect = |message: &Message, parent_message_id: Option<&MessageId>| {
+            if sender.is_some_and(|sender| sender != message.sender) || self.is_deleted(message) {
+                return;
+            }
+            for list_kind in [AttachmentKind::Files, AttachmentKind::Images] {
+                if kind.is_some_and(|kind| kind != list_kind) {
+                    continue;
+                }
+                let attachments = self.attachments_public(message, list_kind);
+                for (index, attachment) in attachments.into_iter().enumerate() {
+                    let matches_mime = mime_prefix.as_ref().is_none_or(|prefix| {
+                        attachment
+                            .mime_type
+                            .to_ascii_lowercase()
+                            .starts_with(prefix.as_str())
+                    });
+                    if !matches_mime {
+                        continue;
+                    }
+                    entries.push(AttachmentEntry {
+                        attachment,
+                        kind: list_kind,
+                        index: index as u32,
+                        message_id: message.id.get().clone(),
+                        parent_message_id: parent_message_id.cloned(),
+                        sender: message.sender,
+                        sender_username: message.sender_username.get().clone(),
+                        timestamp: *message.timestamp.get(),
+                    });
+                }
+            }
+        };
+
+        if let Ok(iter) = self.messages.iter() {
+            for message in iter {
+                // Broadcast references carry no attachments of their own.
+                if self.broadcast_root_of(message.id.get()).is_none() {
+                    collect(&message, None);
+                }
+            }
+        }
+        if let Ok(entries) = self.threads.entries() {
+            for (parent_id, thread) in entries {
+                if let Ok(iter) = thread.iter() {
+                    for reply in iter {
+                        collect(&reply, Some(&parent_id));
+                    }
+                }
+            }
+        }
+
+        paginate_attachments(entries, limit, cursor.as_deref())
+    }
+
+    /// Public view of one of `message`'s attachment lists with its
+    /// `MediaMetadata` filled in. `Message`'s own serialization has no state
+    /// access and leaves `media` empty; views built here include it.
+    fn attachments_public(&self, message: &Message, kind: AttachmentKind) -> Vec<AttachmentPublic> {
+        let list = match kind {
+            AttachmentKind::Files => &message.files,
+            AttachmentKind::Images => &message.images,
+        };
+        let media = self.message_media_of(message.id.get());
+        let mut attachments = attachments_vector_to_public(list);
+        for (index, attachment) in attachments.iter_mut().enumerate() {
+            attachment.media = media.get(kind, index);
+        }
+        attachments
+    }
+
+    fn message_media_of(&self, message_id: &str) -> MessageMedia {
+        match self.attachment_media.get(message_id) {
+            Ok(Some(media)) => media.get().clone(),
+            _ => MessageMedia::default(),
+        }
+    }
+
+    fn set_message_media(&mut self, message_id: &str, media: MessageMedia) {
+        if media.is_empty() {
+            let _ = self.attachment_media.remove(message_id);
+        } else {
+            let _ = self
+                .attachment_media
+                .insert(message_id.to_string(), LwwRegister::new(media));
+        }
+    }
+
+    /// Validate the media metadata of new attachments and announce their
+    /// thumbnails to this context. Like the attachment itself, a thumbnail
+    /// that can't be announced is an error.
+    fn announce_media<'a>(
+        &self,
+        inputs: impl Iterator<Item = &'a AttachmentInput>,
+    ) -> Result<(), String> {
+        let context_id = env::context_id();
+        for input in inputs {
+            let Some(ref media) = input.media else {
+                continue;
+            };
+            validate_media(media)?;
+            if let Some(ref thumbnail) = media.thumbnail_blob_id {
+                let blob_id = parse_blob_id_base58(thumbnail)?;
+                if !env::blob_announce_to_context(&blob_id, &context_id) {
+                    return Err(format!(
+                        "Failed to announce thumbnail {} to this context",
+                        thumbnail
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Bytes of attachments on `user`'s live messages, channel and threads.
+    /// Computed from the messages themselves rather than kept as a running
+    /// total, so concurrent sends, edits and deletes can't make it drift.
+    fn attachment_usage_of(&self, user: &UserId) -> u64 {
+        let bytes = |message: &Message| -> u64 {
+            if message.sender != *user || self.is_deleted(message) {
+                return 0;
+            }
+            message
+                .files
+                .iter()
+                .into_iter()
+                .flatten()
+                .chain(message.images.iter().into_iter().flatten())
+                .map(|attachment| attachment.size)
+                .sum()
+        };
+
+        let mut used: u64 = 0;
+        if let Ok(iter) = self.messages.iter() {
+            for message in iter {
+                // Broadcast references repeat the thread reply's attachments.
+                if self.broadcast_root_of(message.id.get()).is_none() {
+                    used = used.saturating_add(bytes(&message));
+                }
+            }
+        }
+        if let Ok(threads) = self.threads.entries() {
+            for (_, thread) in threads {
+                if let Ok(iter) = thread.iter() {
+                    for reply in iter {
+                        used = used.saturating_add(bytes(&reply));
+                    }
+                }
+            }
+        }
+        used
+    }
+
+    fn check_attachments(
+        &self,
+        sender: &UserId,
+        files: &Option<Vec<AttachmentInput>>,
+        images: &Option<Vec<AttachmentInput>>,
+    ) -> Result<(), String> {
+        let all: Vec<&AttachmentInput> = files
+            .iter()
+            .flatten()
+            .chain(images.iter().flatten())
+            .collect();
+        if all.is_empty() {
+            return Ok(());
+        }
+        self.attachment_policy
+            .get()
+            .check(&all, self.attachment_usage_of(sender))?;
+        Ok(())
+    }
+
+    // ── Member roster ──────────────────────────────────────────────────────
+
+    /// Add the caller to the roster; call once after joining the context.
+    /// Idempotent while present. After a `leave`, re-registering starts a
+    /// new membership with a fresh `joined_at`. `invited_by` must be another
+    /// known member.
+    pub fn register_member(&mut self, invited_by: Option<UserId>) -> app::Result<String, String> {
+        self.require_not_banned()?;
+        let caller = Self::executor_id();
+        if let Some(inviter) = invited_by {
+            if inviter == caller {
+                return Err("You cannot be your own inviter".to_string());
+            }
+            if !self.known_members().contains(&inviter) {
+                return Err("Inviter is not a member of this context".to_string());
+            }
+        }
+
+        match self.members.get(&caller) {
+            Ok(Some(existing)) if existing.left_at.get().is_none() => {
+                return Ok("Already a member".to_string());
+            }
+            Ok(Some(mut existing)) => {
+                existing.joined_at.set(env::time_now());
+                existing.invited_by.set(invited_by);
+                existing.left_at.set(None);
+                let _ = self.members.update(&caller, existing);
+            }
+            _ => {
+                let member = StoredMember {
+                    joined_at: LwwRegister::new(env::time_now()),
+                    invited_by: LwwRegister::new(invited_by),
+                    left_at: LwwRegister::new(None),
+                };
+                let _ = self.members.insert(caller, member);
+            }
+        }
+
+        app::emit!(Event::MemberUpdated(caller.to_string()));
+        Ok("Member registered".to_string())
+    }
+
+    /// Mark the caller as having left. The roster entry is kept (with
+    /// `left_at`) so history and `invited_by` stay attributable.
+    pub fn leave(&mut self) -> app::Result<String, String> {
+        let caller = Self::executor_id();
+        let mut member = match self.members.get(&caller) {
+            Ok(Some(member)) => member,
+            _ => return Err("You are not a member of this context".to_string()),
+        };
+        if member.left_at.get().is_some() {
+            return Ok("Already left".to_string());
+        }
+        member.left_at.set(Some(env::time_now()));
+        let _ = self.members.update(&caller, member);
+
+        app::emit!(Event::MemberUpdated(caller.to_string()));
+        Ok("Left".to_string())
+    }
+
+    /// Roster joined with role and presence, plus the caller's own unread
+    /// counts on their row.
+    pub fn get_members(&self, filter: Option<MemberFilter>) -> Vec<MemberInfo> {
+        let filter = filter.unwrap_or_default();
+        let include_left = filter.include_left.unwrap_or(false);
+        let caller = Self::executor_id();
+        let now = env::time_now();
+
+        let mut result = Vec::new();
+        for identity in self.known_members() {
+            let member = self.members.get(&identity).ok().flatten();
+            let left_at = member.as_ref().and_then(|m| *m.left_at.get());
+            if left_at.is_some() && !include_left {
+                continue;
+            }
+
+            let role = self.role_of(&identity);
+            if filter.role.is_some_and(|r| r != role) {
+                continue;
+            }
+            let presence = self.presence_of(&identity, &caller, now);
+            if filter.presence.is_some_and(|p| p != presence.status) {
+                continue;
+            }
+
+            let unread = (identity == caller).then(|| self.unread_summary_for(&identity));
+            result.push(MemberInfo {
+                identity,
+                username: presence.username,
+                role,
+                presence: presence.status,
+                last_active: presence.last_active,
+                joined_at: member.as_ref().map(|m| *m.joined_at.get()),
+                invited_by: member.as_ref().and_then(|m| *m.invited_by.get()),
+                left_at,
+                unread_count: unread.as_ref().map(|s| s.channel_count + s.thread_count),
+                unread_mentions: unread.as_ref().map(|s| s.mention_count),
+            });
+        }
+        result
+    }
+
+    /// Everyone the context knows about: the roster, plus members inferred
+    /// from profiles and heartbeats who predate the roster.
+    fn known_members(&self) -> Vec<UserId> {
+        let mut members: Vec<UserId> = Vec::new();
+        if let Ok(entries) = self.members.entries() {
+            members.extend(entries.map(|(id, _)| id));
+        }
+        if let Ok(entries) = self.profiles.entries() {
+            for (id, _) in entries {
+                if !members.contains(&id) {
+                    members.push(id);
                 }
-
-                // Direct mention: caller's UserId is in the message's mentions set.
-                if let Ok(mut mentions) = msg.mentions.iter() {
-                    if mentions.any(|uid| uid == caller) {
-                        count += 1;
-                    }
+            }
+        }
+        if let Ok(entries) = self.last_active.entries() {
+            for (id, _) in entries {
+                if !members.contains(&id) {
+                    members.push(id);
                 }
             }
         }
-        count
+        members
     }
 
-    pub fn update_info(
-        &mut self,
-        name: Option<String>,
-        description: Option<String>,
-    ) -> app::Result<String, String> {
-        self.require_not_banned()?;
-        if let Some(n) = name {
-            self.name.set(n);
+    /// `known_members` without those who have left.
+    fn current_members(&self) -> Vec<UserId> {
+        self.known_members()
+            .into_iter()
+            .filter(|id| match self.members.get(id) {
+                Ok(Some(member)) => member.left_at.get().is_none(),
+                _ => true,
+            })
+            .collect()
+    }
+
+    // ── Presence ───────────────────────────────────────────────────────────
+
+    /// Record that the caller is active. No event is emitted — like
+    /// `mark_as_read`, this is a silent CRDT write so frequent heartbeats
+    /// don't wake every subscriber. Skipped while the caller is invisible,
+    /// since `last_active` replicates to every node.
+    pub fn heartbeat(&mut self) -> app::Result<String, String> {
+        let caller = Self::executor_id();
+        if self.is_invisible(&caller) {
+            return Ok("ok".to_string());
         }
-        if let Some(d) = description {
-            self.description.set(d);
+        let _ = self
+            .last_active
+            .insert(caller, LwwRegister::new(env::time_now()));
+        Ok("ok".to_string())
+    }
+
+    /// Opt in or out of invisible mode: others see the caller as offline.
+    /// Going invisible also drops the caller's last heartbeat. Silent write,
+    /// like `heartbeat`.
+    pub fn set_invisible(&mut self, invisible: bool) -> app::Result<String, String> {
+        let caller = Self::executor_id();
+        if invisible {
+            let _ = self.invisible.insert(caller, LwwRegister::new(true));
+            let _ = self.last_active.remove(&caller);
+        } else {
+            let _ = self.invisible.remove(&caller);
         }
-        app::emit!(Event::InfoUpdated());
-        Ok("Info updated".to_string())
+        Ok("ok".to_string())
     }
 
-    /// Set or update this user's profile in the current context.
-    ///
-    /// Username is **write-once**: once a profile exists for an identity,
-    /// the `username` field is preserved on subsequent calls — only the
-    /// avatar can be changed. This freezes a member's handle to whatever
-    /// they registered at first profile creation (typically on join), so
-    /// other members keep seeing a stable identity even if the user later
-    /// rotates their local `chat-username` from a different device.
-    ///
-    /// Caveat: enforcement is per-node-state at write time, not at the
-    /// CRDT merge layer. Two nodes that each see a freshly-empty profile
-    /// for the same identity and write different usernames concurrently
-    /// will still converge via `LwwRegister` semantics. In practice the
-    /// initial set happens on one device, so this is rare.
-    pub fn set_profile(
+    /// Admin-only: tune how recent a heartbeat must be to count as online
+    /// or away.
+    pub fn set_presence_thresholds(
         &mut self,
-        username: String,
-        avatar: Option<String>,
+        online_within_secs: u64,
+        away_within_secs: u64,
     ) -> app::Result<String, String> {
-        self.require_not_banned()?;
-        if username.trim().is_empty() {
-            return Err("Username cannot be empty".to_string());
+        if self.role_of(&Self::executor_id()) != Role::Admin {
+            return Err("Only admins can change presence thresholds".to_string());
         }
-        if username.len() > 50 {
-            return Err("Username cannot be longer than 50 characters".to_string());
+        if online_within_secs == 0 || away_within_secs < online_within_secs {
+            return Err("Thresholds must satisfy 0 < online <= away".to_string());
         }
+        self.presence_online_within_secs.set(online_within_secs);
+        self.presence_away_within_secs.set(away_within_secs);
+        app::emit!(Event::InfoUpdated());
+        Ok("Presence thresholds updated".to_string())
+    }
 
-        let executor_id = Self::executor_id();
+    /// Presence of every known member (see `known_members`).
+    pub fn get_presence(&self) -> Vec<MemberPresence> {
+        let caller = Self::executor_id();
+        let now = env::time_now();
 
-        if self.profiles.contains(&executor_id).unwrap_or(false) {
-            // Preserve the frozen username; only the avatar is mutable.
-            if let Ok(Some(existing)) = self.profiles.get(&executor_id) {
-                let frozen_username = existing.username.get().clone();
-                let new_profile = StoredProfile {
-                    username: LwwRegister::new(frozen_username),
-                    avatar: avatar.map(LwwRegister::new),
-                };
-                let _ = self.profiles.update(&executor_id, new_profile);
+        self.known_members()
+            .into_iter()
+            .map(|identity| self.presence_of(&identity, &caller, now))
+            .collect()
+    }
+
+    /// Announce that the caller is typing in the channel (`None`) or in the
+    /// thread rooted at `parent_id`. Calls within `TYPING_MIN_INTERVAL_SECS`
+    /// of the previous emission are accepted but dropped, so clients can
+    /// call this on every keystroke.
+    pub fn set_typing(&mut self, parent_id: Option<MessageId>) -> app::Result<String, String> {
+        self.require_not_banned()?;
+        let caller = Self::executor_id();
+        let now = env::time_now();
+
+        if let Ok(Some(last)) = self.typing_last_emitted.get(&caller) {
+            let min_interval = TYPING_MIN_INTERVAL_SECS * NANOS_PER_SEC;
+            if now.saturating_sub(*last.get()) < min_interval {
+                return Ok("throttled".to_string());
             }
-        } else {
-            let profile = StoredProfile {
-                username: LwwRegister::new(username),
-                avatar: avatar.map(LwwRegister::new),
-            };
-            let _ = self.profiles.insert(executor_id, profile);
         }
 
-        app::emit!(Event::ProfileUpdated(executor_id.to_string()));
-        Ok("Profile set".to_string())
+        let _ = self
+            .typing_last_emitted
+            .insert(caller, LwwRegister::new(now));
+        app::emit!(Event::Typing(TypingEvent {
+            identity: caller.to_string(),
+            parent_message_id: parent_id,
+        }));
+        Ok("ok".to_string())
     }
 
-    pub fn get_profiles(&self) -> Vec<UserProfile> {
-        let mut result = Vec::new();
-        if let Ok(entries) = self.profiles.entries() {
-            for (user_id, profile) in entries {
-                result.push(UserProfile {
-                    identity: user_id,
-                    username: profile.username.get().clone(),
-                    avatar: profile.avatar.as_ref().map(|a| a.get().clone()),
-                });
-            }
+    fn is_invisible(&self, identity: &UserId) -> bool {
+        self.invisible
+            .get(identity)
+            .ok()
+            .flatten()
+            .map(|r| *r.get())
+            .unwrap_or(false)
+    }
+
+    fn presence_of(&self, identity: &UserId, viewer: &UserId, now: u64) -> MemberPresence {
+        let invisible = self.is_invisible(identity);
+        let hidden = invisible && identity != viewer;
+
+        let last_active = if hidden {
+            None
+        } else {
+            self.last_active
+                .get(identity)
+                .ok()
+                .flatten()
+                .map(|r| *r.get())
+        };
+
+        MemberPresence {
+            identity: *identity,
+            username: match self.profiles.get(identity) {
+                Ok(Some(profile)) => Some(profile.username.get().clone()),
+                _ => None,
+            },
+            status: presence_status(
+                last_active,
+                now,
+                *self.presence_online_within_secs,
+                *self.presence_away_within_secs,
+            ),
+            last_active,
+            // Only reveal the flag to its owner.
+            invisible: invisible && !hidden,
         }
-        result
     }
 
     // ── Moderation: roles + ban gate ───────────────────────────────────────
@@ -792,6 +3388,182 @@ impl MeroChat {
         }
     }
 
+    fn thread_state_of(&self, parent_id: &str) -> ThreadState {
+        match self.thread_states.get(parent_id) {
+            Ok(Some(s)) => *s.get(),
+            _ => ThreadState::default(),
+        }
+    }
+
+    fn can_broadcast_mention(actor: Role, policy: BroadcastMentionPolicy, is_dm: bool) -> bool {
+        match actor {
+            Role::Banned => false,
+            _ if is_dm => true,
+            Role::Admin => true,
+            Role::Mod => policy != BroadcastMentionPolicy::Admins,
+            Role::User => policy == BroadcastMentionPolicy::Everyone,
+        }
+    }
+
+    /// Admin-only: choose who may use @everyone / @here in this channel.
+    pub fn set_broadcast_mention_policy(
+        &mut self,
+        policy: BroadcastMentionPolicy,
+    ) -> app::Result<String, String> {
+        if self.role_of(&Self::executor_id()) != Role::Admin {
+            return Err("Only admins can change who may mention @everyone".to_string());
+        }
+        self.broadcast_mention_policy.set(policy);
+        app::emit!(Event::InfoUpdated());
+        Ok("Broadcast mention policy updated".to_string())
+    }
+
+    /// Turn client-supplied mentions into the stored pair of lists. IDs are
+    /// kept only if they belong to a member who hasn't left; usernames are
+    /// resolved with `user_by_username`, and the stored usernames are re-derived
+    /// from profiles so the two lists always agree. Broadcast mentions are
+    /// kept only if `sender` may use them, and are an error otherwise.
+    fn resolve_mentions(
+        &self,
+        sender: &UserId,
+        mentions: &[UserId],
+        mentions_usernames: &[String],
+    ) -> Result<(Vec<UserId>, Vec<String>), String> {
+        let members = self.current_members();
+        let mut ids: Vec<UserId> = Vec::new();
+        let mut broadcasts: Vec<String> = Vec::new();
+
+        for id in mentions {
+            if members.contains(id) && !ids.contains(id) {
+                ids.push(*id);
+            }
+        }
+        for name in mentions_usernames {
+            let key = normalize_username(name.trim_start_matches('@'));
+            if BROADCAST_MENTIONS.contains(&key.as_str()) {
+                if !broadcasts.contains(&key) {
+                    broadcasts.push(key);
+                }
+                continue;
+            }
+            if let Some(id) = self.user_by_username(&key) {
+                if members.contains(&id) && !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+
+        if !broadcasts.is_empty() && !self.may_broadcast_mention(sender) {
+            return Err("You don't have permission to mention @everyone or @here".to_string());
+        }
+
+        let mut usernames: Vec<String> = ids
+            .iter()
+            .filter_map(|id| match self.profiles.get(id) {
+                Ok(Some(profile)) => Some(profile.username.get().clone()),
+                _ => None,
+            })
+            // A name taken before broadcast names were reserved must not
+            // turn a direct mention into a broadcast.
+            .filter(|name| !BROADCAST_MENTIONS.contains(&normalize_username(name).as_str()))
+            .collect();
+        usernames.extend(broadcasts);
+        Ok((ids, usernames))
+    }
+
+    fn may_broadcast_mention(&self, sender: &UserId) -> bool {
+        let is_dm = *self.context_type.get() == ContextType::Dm;
+        Self::can_broadcast_mention(self.role_of(sender), *self.broadcast_mention_policy, is_dm)
+    }
+
+    /// Parse `text` and resolve its references. Explicit `mentions` /
+    /// `mentions_usernames`, when either is given, replace the parsed
+    /// mentions entirely. Otherwise a typed `@everyone` the sender may not
+    /// use is left as plain text rather than rejecting the message, and when
+    /// re-deriving an edit, mentions from `previous` survive as long as the
+    /// new text still contains their `@username` — this keeps names the
+    /// parser can't tokenize, such as ones with spaces.
+    fn message_markup(
+        &self,
+        sender: &UserId,
+        text: &str,
+        mentions: Option<Vec<UserId>>,
+        mentions_usernames: Option<Vec<String>>,
+        previous: Option<&Message>,
+    ) -> Result<MessageMarkup, String> {
+        let refs = parse_message_refs(text);
+
+        let (ids, usernames) = if mentions.is_some() || mentions_usernames.is_some() {
+            (
+                mentions.unwrap_or_default(),
+                mentions_usernames.unwrap_or_default(),
+            )
+        } else {
+            let mut ids = Vec::new();
+            if let Some(Ok(iter)) = previous.map(|previous| previous.mentions.iter()) {
+                for id in iter {
+                    let still_there = match self.profiles.get(&id) {
+                        Ok(Some(profile)) => mentions_handle(text, profile.username.get()),
+                        _ => false,
+                    };
+                    if still_there {
+                        ids.push(id);
+                    }
+                }
+            }
+            let may_broadcast = self.may_broadcast_mention(sender);
+            let usernames = refs
+                .mentions
+                .into_iter()
+                .filter(|name| {
+                    may_broadcast
+                        || !BROADCAST_MENTIONS.contains(&normalize_username(name).as_str())
+                })
+                .collect();
+            (ids, usernames)
+        };
+
+        let (mentions, mentions_usernames) = self.resolve_mentions(sender, &ids, &usernames)?;
+        Ok(MessageMarkup {
+            mentions,
+            mentions_usernames,
+            tags: MessageTags {
+                hashtags: refs.hashtags,
+                channel_refs: refs.channels,
+            },
+        })
+    }
+
+    /// Look up the holder of a normalized username. Concurrent claims can
+    /// leave several profiles with the same name; like `get_profiles`, the
+    /// lowest `UserId` among them wins, whatever the index says. The index
+    /// is only consulted when no profile carries the name.
+    fn user_by_username(&self, key: &str) -> Option<UserId> {
+        let holder = self.profiles.entries().ok().and_then(|entries| {
+            entries
+                .filter(|(_, profile)| normalize_username(profile.username.get()) == key)
+                .map(|(id, _)| id)
+                .min()
+        });
+        holder.or_else(|| match self.username_index.get(key) {
+            Ok(Some(owner)) => Some(*owner.get()),
+            _ => None,
+        })
+    }
+
+    fn can_set_thread_state(
+        actor: Role,
+        is_author: bool,
+        current: ThreadState,
+        new: ThreadState,
+    ) -> bool {
+        match actor {
+            Role::Admin | Role::Mod => true,
+            Role::User => is_author && current != ThreadState::Locked && new != ThreadState::Locked,
+            Role::Banned => false,
+        }
+    }
+
     fn executor_id() -> UserId {
         UserId::new(env::executor_id())
     }
@@ -830,20 +3602,41 @@ impl MeroChat {
         }
     }
 
+    /// Mentions, hashtags and channel references are parsed from `message`.
+    /// Passing `mentions` / `mentions_usernames` overrides the parsed
+    /// mentions (e.g. for usernames containing spaces); either way they are
+    /// validated by `resolve_mentions`.
     pub fn send_message(
         &mut self,
         message: String,
-        mentions: Vec<UserId>,
-        mentions_usernames: Vec<String>,
+        mentions: Option<Vec<UserId>>,
+        mentions_usernames: Option<Vec<String>>,
         parent_message: Option<MessageId>,
         timestamp: u64,
         sender_username: String,
         files: Option<Vec<AttachmentInput>>,
         images: Option<Vec<AttachmentInput>>,
+        also_send_to_channel: Option<bool>,
+        reply_to: Option<MessageId>,
     ) -> app::Result<Message, String> {
         self.require_not_banned()?;
         let executor_id = Self::executor_id();
 
+        let also_send_to_channel = also_send_to_channel.unwrap_or(false);
+        if also_send_to_channel && parent_message.is_none() {
+            return Err("also_send_to_channel is only valid for thread replies".to_string());
+        }
+        if let Some(ref parent_id) = parent_message {
+            if self.thread_state_of(parent_id) == ThreadState::Locked {
+                return Err("This thread is locked".to_string());
+            }
+        }
+        if let Some(ref target_id) = reply_to {
+            if self.find_any_message(target_id).is_none() {
+                return Err("Message being replied to was not found".to_string());
+            }
+        }
+
         let sender_username = match self.profiles.get(&executor_id) {
             Ok(Some(profile)) => profile.username.get().clone(),
             _ => sender_username,
@@ -852,24 +3645,21 @@ impl MeroChat {
         let message_id = self.get_message_id(&executor_id, &message, timestamp);
         let current_context = env::context_id();
 
+        self.check_attachments(&executor_id, &files, &images)?;
+        self.announce_media(files.iter().flatten().chain(images.iter().flatten()))?;
+        let media = MessageMedia::from_inputs(&files, &images);
         let files_vector = attachment_inputs_to_vector(files, &current_context)?;
         let images_vector = attachment_inputs_to_vector(images, &current_context)?;
 
-        let mut mentions_set = UnorderedSet::new();
-        for m in &mentions {
-            let _ = mentions_set.insert(*m);
-        }
-        let mut mentions_usernames_vec = Vector::new();
-        for m in &mentions_usernames {
-            let _ = mentions_usernames_vec.push(LwwRegister::new(m.clone()));
-        }
+        let markup =
+            self.message_markup(&executor_id, &message, mentions, mentions_usernames, None)?;
 
-        let msg = Message {
+        let mut msg = Message {
             timestamp: LwwRegister::new(timestamp),
             sender: executor_id,
             sender_username: LwwRegister::new(sender_username),
-            mentions: mentions_set,
-            mentions_usernames: mentions_usernames_vec,
+            mentions: UnorderedSet::new(),
+            mentions_usernames: Vector::new(),
             files: files_vector,
             images: images_vector,
             id: LwwRegister::new(message_id.clone()),
@@ -877,6 +3667,30 @@ impl MeroChat {
             deleted: None,
             edited_on: None,
         };
+        markup.apply_to(&mut msg);
+        self.set_message_tags(&message_id, markup.tags);
+        self.set_message_media(&message_id, media);
+
+        if let Some(target_id) = reply_to {
+            let _ = self
+                .reply_targets
+                .insert(message_id, LwwRegister::new(target_id));
+        }
+        self.store_message(&msg, parent_message, also_send_to_channel);
+        Ok(msg)
+    }
+
+    /// Append `msg` to the channel timeline or to `parent_message`'s thread
+    /// and emit the matching events.
+    fn store_message(
+        &mut self,
+        msg: &Message,
+        parent_message: Option<MessageId>,
+        also_send_to_channel: bool,
+    ) {
+        let sender = msg.sender;
+        let timestamp = *msg.timestamp;
+        let message_id = msg.id.get().clone();
 
         if let Some(parent_id) = parent_message {
             let mut thread_messages = match self.threads.get(&parent_id) {
@@ -884,7 +3698,29 @@ impl MeroChat {
                 _ => AuthoredVector::new(),
             };
             let _ = thread_messages.push(msg.clone());
-            let _ = self.threads.insert(parent_id, thread_messages);
+            let _ = self.threads.insert(parent_id.clone(), thread_messages);
+
+            // Replying makes the sender a participant (implicit follower)
+            // and means they've read the thread up to their own reply.
+            self.add_thread_participant(&parent_id, sender);
+            self.set_thread_last_read(sender, &parent_id, timestamp);
+
+            // The channel copy is a reference: same ID, no body of its own.
+            // Mentions are kept so the channel's unread/mention counts see it.
+            if also_send_to_channel {
+                let mut reference = msg.clone();
+                reference.text.set(String::new());
+                reference.files = Vector::new();
+                reference.images = Vector::new();
+                let _ = self.messages.push(reference);
+                let _ = self
+                    .broadcast_roots
+                    .insert(message_id.clone(), LwwRegister::new(parent_id.clone()));
+
+                app::emit!(Event::MessageSent(MessageSentEvent {
+                    message_id: message_id.clone(),
+                }));
+            }
 
             app::emit!(Event::MessageSentThread(MessageSentEvent {
                 message_id: message_id.clone(),
@@ -896,7 +3732,119 @@ impl MeroChat {
                 message_id: message_id.clone(),
             }));
         }
+    }
+
+    /// Build the record a client needs to forward `message_id` into another
+    /// context. Read-only; pair with `forward_message` in the target context.
+    pub fn get_forward_payload(
+        &self,
+        message_id: MessageId,
+    ) -> app::Result<ForwardPayload, String> {
+        let message = self
+            .find_any_message(&message_id)
+            .ok_or_else(|| "Message not found".to_string())?;
+        if self.is_deleted(&message) {
+            return Err("Cannot forward a deleted message".to_string());
+        }
+
+        let mut payload = ForwardPayload {
+            provenance: ForwardProvenance {
+                source_context_id: encode_blob_id_base58(&env::context_id()),
+                original_message_id: message_id,
+                original_sender: message.sender,
+                original_sender_username: message.sender_username.get().clone(),
+                original_timestamp: *message.timestamp,
+                digest: String::new(),
+                verified: false,
+            },
+            text: message.text.get().clone(),
+            files: self.attachments_public(&message, AttachmentKind::Files),
+            images: self.attachments_public(&message, AttachmentKind::Images),
+        };
+        payload.provenance.digest = payload.compute_digest();
+        Ok(payload)
+    }
+
+    /// Post a message forwarded from another context. The payload comes from
+    /// `get_forward_payload` in the source context; its attachments are
+    /// re-announced into this context so members here can fetch the blobs.
+    ///
+    /// When the source is this context, the payload is rebuilt from the
+    /// original message and marked `verified`. Otherwise it is stored as
+    /// the client sent it, unverified; see `ForwardProvenance`.
+    pub fn forward_message(
+        &mut self,
+        payload: ForwardPayload,
+        parent_message: Option<MessageId>,
+        timestamp: u64,
+        sender_username: String,
+    ) -> app::Result<Message, String> {
+        self.require_not_banned()?;
+        let this_context = encode_blob_id_base58(&env::context_id());
+        let payload = if payload.provenance.source_context_id == this_context {
+            let mut local = self.get_forward_payload(payload.provenance.original_message_id)?;
+            local.provenance.verified = true;
+            local
+        } else {
+            if payload.compute_digest() != payload.provenance.digest {
+                return Err("Forward provenance digest does not match its contents".to_string());
+            }
+            let mut payload = payload;
+            payload.provenance.verified = false;
+            payload
+        };
+        if let Some(ref parent_id) = parent_message {
+            if self.thread_state_of(parent_id) == ThreadState::Locked {
+                return Err("This thread is locked".to_string());
+            }
+        }
+
+        let executor_id = Self::executor_id();
+        let sender_username = match self.profiles.get(&executor_id) {
+            Ok(Some(profile)) => profile.username.get().clone(),
+            _ => sender_username,
+        };
+
+        let message_id = self.get_message_id(&executor_id, &payload.text, timestamp);
+        let current_context = env::context_id();
+
+        let files = attachments_public_to_inputs(&payload.files);
+        let images = attachments_public_to_inputs(&payload.images);
+        self.check_attachments(&executor_id, &files, &images)?;
+        self.announce_media(files.iter().flatten().chain(images.iter().flatten()))?;
+        let media = MessageMedia::from_inputs(&files, &images);
+        let files_vector = attachment_inputs_to_vector(files, &current_context)?;
+        let images_vector = attachment_inputs_to_vector(images, &current_context)?;
+
+        // Mentions belong to the source context's members; they are not
+        // carried over. Hashtags and channel references are re-parsed.
+        let refs = parse_message_refs(&payload.text);
+        let msg = Message {
+            timestamp: LwwRegister::new(timestamp),
+            sender: executor_id,
+            sender_username: LwwRegister::new(sender_username),
+            mentions: UnorderedSet::new(),
+            mentions_usernames: Vector::new(),
+            files: files_vector,
+            images: images_vector,
+            id: LwwRegister::new(message_id.clone()),
+            text: LwwRegister::new(payload.text),
+            deleted: None,
+            edited_on: None,
+        };
+        self.set_message_tags(
+            &message_id,
+            MessageTags {
+                hashtags: refs.hashtags,
+                channel_refs: refs.channels,
+            },
+        );
+        self.set_message_media(&message_id, media);
+        let _ = self
+            .forward_origins
+            .insert(message_id, LwwRegister::new(payload.provenance));
 
+        self.store_message(&msg, parent_message, false);
         Ok(msg)
     }
 
@@ -925,6 +3873,7 @@ impl MeroChat {
                 &thread_messages,
                 normalized_search.as_deref(),
                 false,
+                false,
             );
             return Ok(Self::paginate(filtered, limit, offset));
         }
@@ -933,6 +3882,7 @@ impl MeroChat {
             &self.messages,
             normalized_search.as_deref(),
             true,
+            true,
         );
         Ok(Self::paginate(filtered, limit, offset))
     }
@@ -946,12 +3896,12 @@ impl MeroChat {
         let normalized = search_term.to_lowercase();
         let term = normalized.as_str();
 
-        let mut all = self.collect_messages_with_reactions(&self.messages, Some(term), false);
+        let mut all = self.collect_messages_with_reactions(&self.messages, Some(term), true, false);
 
         if let Ok(entries) = self.threads.entries() {
             for (parent_id, thread) in entries {
                 let mut thread_results =
-                    self.collect_messages_with_reactions(&thread, Some(term), false);
+                    self.collect_messages_with_reactions(&thread, Some(term), false, false);
                 for msg in thread_results.iter_mut() {
                     msg.parent_message_id = Some(parent_id.clone());
                 }
@@ -963,15 +3913,35 @@ impl MeroChat {
         Ok(Self::paginate(all, limit, offset))
     }
 
+    /// `channel` is set when `messages` is the channel timeline; only there
+    /// can an entry be a broadcast reference (thread replies share its ID).
     fn collect_messages_with_reactions(
         &self,
         messages: &AuthoredVector<Message>,
         search_term: Option<&str>,
+        channel: bool,
         include_threads: bool,
     ) -> Vec<MessageWithReactions> {
         let mut result = Vec::new();
         if let Ok(iter) = messages.iter() {
             for message in iter {
+                // Channel references to thread replies render the reply itself,
+                // so edits made in the thread show up here too. Outside the
+                // channel timeline they are skipped: the thread copy is
+                // already listed (e.g. by `search_all_messages`).
+                let broadcast_root = if channel {
+                    self.broadcast_root_of(message.id.get())
+                } else {
+                    None
+                };
+                let message = match broadcast_root {
+                    Some(ref root) if include_threads => self
+                        .find_thread_reply(root, message.id.get())
+                        .unwrap_or(message),
+                    Some(_) => continue,
+                    None => message,
+                };
+
                 if !Self::message_matches_search(&message, search_term) {
                     continue;
                 }
@@ -1008,6 +3978,12 @@ impl MeroChat {
                     message.text.get().clone()
                 };
 
+                // Tags are derived from the text, so they go with it.
+                let tags = if is_deleted {
+                    MessageTags::default()
+                } else {
+                    self.message_tags_of(&msg_id)
+                };
                 result.push(MessageWithReactions {
                     timestamp: *message.timestamp,
                     sender: message.sender,
@@ -1016,14 +3992,21 @@ impl MeroChat {
                     text,
                     mentions: mentions_vec,
                     mentions_usernames: mentions_usernames_vec,
-                    files: attachments_vector_to_public(&message.files),
-                    images: attachments_vector_to_public(&message.images),
+                    files: self.attachments_public(&message, AttachmentKind::Files),
+                    images: self.attachments_public(&message, AttachmentKind::Images),
                     reactions,
                     deleted: if is_deleted { Some(true) } else { message.deleted.as_ref().map(|r| **r) },
                     edited_on: message.edited_on.as_ref().map(|r| **r),
                     thread_count,
                     thread_last_timestamp,
-                    parent_message_id: None,
+                    thread_state: self.thread_state_of(message.id.get()),
+                    parent_message_id: broadcast_root,
+                    reply_to: self
+                        .reply_target_of(message.id.get())
+                        .map(|id| self.reply_preview(&id)),
+                    forwarded_from: self.forward_origin_of(message.id.get()),
+                    hashtags: tags.hashtags,
+                    channel_refs: tags.channel_refs,
                 });
             }
         }
@@ -1070,10 +4053,229 @@ impl MeroChat {
                 };
                 (count as u32, last_ts)
             }
-            _ => (0, 0),
+            _ => (0, 0),
+        }
+    }
+
+    fn thread_summary(&self, user: &UserId, parent_id: &str) -> ThreadSummary {
+        let (reply_count, last_reply_timestamp) = self.get_thread_info(parent_id);
+        let last_read = self
+            .thread_last_read(user, parent_id)
+            .unwrap_or_else(|| self.last_read(user));
+
+        let participants: Vec<UserId> = match self.thread_participants.get(parent_id) {
+            Ok(Some(set)) => {
+                if let Ok(iter) = set.iter() {
+                    iter.collect()
+                } else {
+                    Vec::new()
+                }
+            }
+            _ => Vec::new(),
+        };
+
+        let mut unread_count = 0u32;
+        if let Ok(Some(thread)) = self.threads.get(parent_id) {
+            if let Ok(iter) = thread.iter() {
+                for reply in iter {
+                    if *reply.timestamp <= last_read || reply.sender == *user {
+                        continue;
+                    }
+                    if self.is_deleted(&reply) {
+                        continue;
+                    }
+                    unread_count += 1;
+                }
+            }
+        }
+
+        ThreadSummary {
+            parent_message_id: parent_id.to_string(),
+            reply_count,
+            last_reply_timestamp,
+            thread_state: self.thread_state_of(parent_id),
+            following: self.is_following_thread(user, parent_id, &participants),
+            participants,
+            last_read_timestamp: last_read,
+            unread_count,
+        }
+    }
+
+    fn is_following_thread(&self, user: &UserId, parent_id: &str, participants: &[UserId]) -> bool {
+        let explicit = match self.thread_follows.get(user) {
+            Ok(Some(follows)) => follows.get(parent_id).ok().flatten().map(|r| *r.get()),
+            _ => None,
+        };
+        explicit.unwrap_or_else(|| participants.contains(user))
+    }
+
+    fn set_thread_follow(&mut self, user: UserId, parent_id: &str, follow: bool) {
+        let mut follows = match self.thread_follows.get(&user) {
+            Ok(Some(follows)) => follows,
+            _ => UnorderedMap::new(),
+        };
+        let _ = follows.insert(parent_id.to_string(), LwwRegister::new(follow));
+        let _ = self.thread_follows.insert(user, follows);
+    }
+
+    fn thread_last_read(&self, user: &UserId, parent_id: &str) -> Option<u64> {
+        match self.thread_read_receipts.get(user) {
+            Ok(Some(receipts)) => receipts.get(parent_id).ok().flatten().map(|r| *r.get()),
+            _ => None,
+        }
+    }
+
+    fn set_thread_last_read(&mut self, user: UserId, parent_id: &str, timestamp: u64) {
+        let mut receipts = match self.thread_read_receipts.get(&user) {
+            Ok(Some(receipts)) => receipts,
+            _ => UnorderedMap::new(),
+        };
+        let _ = receipts.insert(parent_id.to_string(), LwwRegister::new(timestamp));
+        let _ = self.thread_read_receipts.insert(user, receipts);
+    }
+
+    /// Record `user` as a participant of the thread rooted at `parent_id`.
+    /// The root's author is added alongside the first reply.
+    fn add_thread_participant(&mut self, parent_id: &str, user: UserId) {
+        let mut participants = match self.thread_participants.get(parent_id) {
+            Ok(Some(set)) => set,
+            _ => {
+                let mut set = UnorderedSet::new();
+                if let Some(root) = self.find_message(parent_id) {
+                    let _ = set.insert(root.sender);
+                }
+                set
+            }
+        };
+        let _ = participants.insert(user);
+        let _ = self
+            .thread_participants
+            .insert(parent_id.to_string(), participants);
+    }
+
+    fn find_thread_reply(&self, parent_id: &str, message_id: &str) -> Option<Message> {
+        let thread = self.threads.get(parent_id).ok().flatten()?;
+        let reply = thread
+            .iter()
+            .ok()?
+            .find(|message| *message.id == *message_id);
+        reply
+    }
+
+    /// Thread root of the channel reference for `message_id`, if that reply
+    /// was sent with `also_send_to_channel`.
+    fn broadcast_root_of(&self, message_id: &str) -> Option<MessageId> {
+        let root = self.broadcast_roots.get(message_id).ok().flatten()?;
+        Some(root.get().clone())
+    }
+
+    fn forward_origin_of(&self, message_id: &str) -> Option<ForwardProvenance> {
+        let origin = self.forward_origins.get(message_id).ok().flatten()?;
+        Some(origin.get().clone())
+    }
+
+    fn message_tags_of(&self, message_id: &str) -> MessageTags {
+        match self.message_tags.get(message_id) {
+            Ok(Some(tags)) => tags.get().clone(),
+            _ => MessageTags::default(),
+        }
+    }
+
+    fn set_message_tags(&mut self, message_id: &str, tags: MessageTags) {
+        let _ = self
+            .message_tags
+            .insert(message_id.to_string(), LwwRegister::new(tags));
+    }
+
+    fn reply_target_of(&self, message_id: &str) -> Option<MessageId> {
+        let target = self.reply_targets.get(message_id).ok().flatten()?;
+        Some(target.get().clone())
+    }
+
+    /// Look up a top-level message by ID.
+    fn find_message(&self, message_id: &str) -> Option<Message> {
+        self.messages
+            .iter()
+            .ok()?
+            .find(|message| *message.id == *message_id)
+    }
+
+    /// Look up a message anywhere in the context: the channel timeline first
+    /// (resolving broadcast references), then every thread.
+    fn find_any_message(&self, message_id: &str) -> Option<Message> {
+        if let Some(message) = self.find_message(message_id) {
+            return match self.broadcast_root_of(message_id) {
+                Some(ref root) => self.find_thread_reply(root, message_id).or(Some(message)),
+                None => Some(message),
+            };
+        }
+        self.threads.entries().ok()?.find_map(|(_, thread)| {
+            let reply = thread
+                .iter()
+                .ok()?
+                .find(|message| *message.id == *message_id);
+            reply
+        })
+    }
+
+    /// Like `find_any_message`, but also returns the thread root when the
+    /// message is a thread reply (`None` for channel messages).
+    fn locate_message(&self, message_id: &str) -> Option<(Option<MessageId>, Message)> {
+        if let Some(message) = self.find_message(message_id) {
+            return match self.broadcast_root_of(message_id) {
+                Some(root) => {
+                    let reply = self.find_thread_reply(&root, message_id)?;
+                    Some((Some(root), reply))
+                }
+                None => Some((None, message)),
+            };
+        }
+        self.threads
+            .entries()
+            .ok()?
+            .find_map(|(parent_id, thread)| {
+                let reply = thread
+                    .iter()
+                    .ok()?
+                    .find(|message| *message.id == *message_id)?;
+                Some((Some(parent_id), reply))
+            })
+    }
+
+    fn reply_preview(&self, target_id: &str) -> ReplyPreview {
+        match self.find_any_message(target_id) {
+            Some(target) => {
+                let deleted = self.is_deleted(&target);
+                ReplyPreview {
+                    message_id: target_id.to_string(),
+                    sender: Some(target.sender),
+                    sender_username: Some(target.sender_username.get().clone()),
+                    snippet: if deleted {
+                        String::new()
+                    } else {
+                        text_snippet(target.text.get(), REPLY_SNIPPET_MAX_CHARS)
+                    },
+                    deleted,
+                }
+            }
+            None => ReplyPreview {
+                message_id: target_id.to_string(),
+                sender: None,
+                sender_username: None,
+                snippet: String::new(),
+                deleted: false,
+            },
         }
     }
 
+    fn is_deleted(&self, message: &Message) -> bool {
+        message.deleted.as_ref().map(|r| **r).unwrap_or(false)
+            || self
+                .deleted_messages
+                .contains(message.id.get())
+                .unwrap_or(false)
+    }
+
     fn paginate(
         filtered: Vec<MessageWithReactions>,
         limit: Option<usize>,
@@ -1142,16 +4344,74 @@ impl MeroChat {
         Ok(format!("Reaction {} successfully", action))
     }
 
+    /// Mentions are re-derived from `new_message`, keeping earlier ones still
+    /// addressed as `@username`. Passing `mentions` / `mentions_usernames`
+    /// replaces the kept ones instead, as in `send_message`. Emits
+    /// `MentionsUpdated` when the mention set changes.
+    ///
+    /// `attachments` adds or removes files and images in place, so reactions
+    /// and the thread survive. Every edit is recorded in `get_edit_history`.
+    /// Deleted messages can't be edited.
+    #[allow(clippy::too_many_arguments)]
     pub fn edit_message(
         &mut self,
         message_id: MessageId,
         new_message: String,
         timestamp: u64,
         parent_id: Option<MessageId>,
+        mentions: Option<Vec<UserId>>,
+        mentions_usernames: Option<Vec<String>>,
+        attachments: Option<AttachmentEdit>,
     ) -> app::Result<Message, String> {
         self.require_not_banned()?;
         let executor_id = Self::executor_id();
 
+        // A thread reply that was also sent to the channel is edited in the
+        // thread; its channel reference picks the change up when rendered.
+        let broadcast_root = self.broadcast_root_of(&message_id);
+        let parent_id = parent_id.or_else(|| broadcast_root.clone());
+
+        let previous = match parent_id {
+            Some(ref parent_id) => self.find_thread_reply(parent_id, &message_id),
+            None => self.find_message(&message_id),
+        };
+        if previous
+            .as_ref()
+            .is_some_and(|previous| self.is_deleted(previous))
+        {
+            return Err("Cannot edit a deleted message".to_string());
+        }
+        let markup = self.message_markup(
+            &executor_id,
+            &new_message,
+            mentions,
+            mentions_usernames,
+            previous.as_ref(),
+        )?;
+        let (mentions_before, broadcast_before) = match previous {
+            Some(ref previous) => (
+                previous
+                    .mentions
+                    .iter()
+                    .map(|iter| iter.collect())
+                    .unwrap_or_default(),
+                previous
+                    .mentions_usernames
+                    .iter()
+                    .map(|mut iter| {
+                        iter.any(|name| BROADCAST_MENTIONS.contains(&name.get().as_str()))
+                    })
+                    .unwrap_or(false),
+            ),
+            None => (Vec::new(), false),
+        };
+        let changes = match (attachments, previous.as_ref()) {
+            (Some(edit), Some(previous)) if previous.sender == executor_id => {
+                self.prepare_attachment_edit(previous, edit)?
+            }
+            _ => AttachmentChanges::default(),
+        };
+
         if let Some(parent_message_id) = parent_id {
             let mut thread_messages = match self.threads.get(&parent_message_id) {
                 Ok(Some(messages)) => messages,
@@ -1164,13 +4424,28 @@ impl MeroChat {
                 &new_message,
                 timestamp,
                 &executor_id,
+                &markup,
+                &changes,
             )?;
 
             let _ = self.threads.insert(parent_message_id, thread_messages);
+            self.set_message_tags(&message_id, markup.tags.clone());
+            if let Some(ref previous) = previous {
+                self.record_edit(previous, timestamp, &changes);
+            }
 
+            if broadcast_root.is_some() {
+                // The channel reference carries its own copy of the mentions
+                // for the channel's unread counts.
+                self.update_reference_mentions(&message_id, &markup);
+                app::emit!(Event::MessageSent(MessageSentEvent {
+                    message_id: updated.id.get().clone(),
+                }));
+            }
             app::emit!(Event::MessageSentThread(MessageSentEvent {
                 message_id: updated.id.get().clone(),
             }));
+            Self::emit_mentions_updated(&message_id, &mentions_before, broadcast_before, &markup);
             Ok(updated)
         } else {
             let updated = Self::find_and_edit(
@@ -1179,21 +4454,154 @@ impl MeroChat {
                 &new_message,
                 timestamp,
                 &executor_id,
+                &markup,
+                &changes,
             )?;
+            self.set_message_tags(&message_id, markup.tags.clone());
+            if let Some(ref previous) = previous {
+                self.record_edit(previous, timestamp, &changes);
+            }
 
             app::emit!(Event::MessageSent(MessageSentEvent {
                 message_id: updated.id.get().clone(),
             }));
+            Self::emit_mentions_updated(&message_id, &mentions_before, broadcast_before, &markup);
             Ok(updated)
         }
     }
 
+    /// Previous versions of a message, oldest first. `delete_message` drops
+    /// the history, and it stays empty for deleted messages even if an edit
+    /// syncs in afterwards, so deleted text can't be read back through here.
+    pub fn get_edit_history(&self, message_id: MessageId) -> Vec<MessageEdit> {
+        match self.find_any_message(&message_id) {
+            Some(message) if !self.is_deleted(&message) => {}
+            _ => return Vec::new(),
+        }
+        match self.edit_history.get(&message_id) {
+            Ok(Some(history)) => history
+                .iter()
+                .map(|iter| iter.collect())
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Validate an `AttachmentEdit` against the policy (with the removed
+    /// attachments' bytes already given back) and announce the new blobs.
+    fn prepare_attachment_edit(
+        &mut self,
+        previous: &Message,
+        edit: AttachmentEdit,
+    ) -> Result<AttachmentChanges, String> {
+        let mut current = attachments_vector_to_public(&previous.files);
+        current.extend(attachments_vector_to_public(&previous.images));
+        let removed = attachments_to_remove(&current, &edit.remove_blob_ids.unwrap_or_default())?;
+
+        let added: Vec<&AttachmentInput> = edit
+            .add_files
+            .iter()
+            .flatten()
+            .chain(edit.add_images.iter().flatten())
+            .collect();
+        let policy = self.attachment_policy.get().clone();
+        let remaining = current.len() - removed.len() + added.len();
+        if remaining > policy.max_attachments_per_message as usize {
+            return Err(format!(
+                "A message can have at most {} attachments",
+                policy.max_attachments_per_message
+            ));
+        }
+        if !added.is_empty() {
+            let freed: u64 = removed.iter().map(|attachment| attachment.size).sum();
+            let used = self
+                .attachment_usage_of(&previous.sender)
+                .saturating_sub(freed);
+            policy.check(&added, used)?;
+        }
+        self.announce_media(added.into_iter())?;
+        let added_media = MessageMedia::from_inputs(&edit.add_files, &edit.add_images);
+
+        let context_id = env::context_id();
+        let announce = |inputs| -> Result<Vec<Attachment>, String> {
+            let vector = attachment_inputs_to_vector(inputs, &context_id)?;
+            Ok(vector.iter().map(|iter| iter.collect()).unwrap_or_default())
+        };
+        Ok(AttachmentChanges {
+            add_files: announce(edit.add_files)?,
+            add_images: announce(edit.add_images)?,
+            added_media,
+            removed,
+        })
+    }
+
+    /// Append the pre-edit state to the message's history and re-index its
+    /// attachment media to match the edited lists.
+    fn record_edit(&mut self, previous: &Message, timestamp: u64, changes: &AttachmentChanges) {
+        let message_id = previous.id.get().clone();
+        let added = changes.added();
+
+        let mut history = match self.edit_history.get(&message_id) {
+            Ok(Some(history)) => history,
+            _ => Vector::new(),
+        };
+        let _ = history.push(MessageEdit {
+            edited_at: timestamp,
+            previous_text: previous.text.get().clone(),
+            added_attachments: added.clone(),
+            removed_attachments: changes.removed.clone(),
+        });
+        let _ = self.edit_history.insert(message_id.clone(), history);
+
+        if !changes.is_empty() {
+            let media = changes.apply_to_media(previous, &self.message_media_of(&message_id));
+            self.set_message_media(&message_id, media);
+        }
+    }
+
+    fn update_reference_mentions(&mut self, message_id: &str, markup: &MessageMarkup) {
+        // Only called for broadcast replies, so the top-level entry with this
+        // ID is the reference.
+        let found = self
+            .messages
+            .iter()
+            .ok()
+            .and_then(|mut iter| iter.position(|message| *message.id == *message_id));
+        if let Some(index) = found {
+            if let Ok(Some(mut reference)) = self.messages.get(index) {
+                markup.apply_to(&mut reference);
+                let _ = self.messages.update(index, reference);
+            }
+        }
+    }
+
+    fn emit_mentions_updated(
+        message_id: &str,
+        before: &[UserId],
+        broadcast_before: bool,
+        markup: &MessageMarkup,
+    ) {
+        let (added, removed) = mention_diff(before, &markup.mentions);
+        let broadcast = markup.has_broadcast();
+        if added.is_empty() && removed.is_empty() && broadcast == broadcast_before {
+            return;
+        }
+        app::emit!(Event::MentionsUpdated(MentionsUpdatedEvent {
+            message_id: message_id.to_string(),
+            added,
+            removed,
+            broadcast,
+        }));
+    }
+
     fn find_and_edit(
         messages: &mut AuthoredVector<Message>,
         message_id: &str,
         new_text: &str,
         timestamp: u64,
         executor_id: &UserId,
+        markup: &MessageMarkup,
+        attachments: &AttachmentChanges,
     ) -> Result<Message, String> {
         let mut target_index: Option<usize> = None;
 
@@ -1219,6 +4627,8 @@ impl MeroChat {
         let mut updated = original.clone();
         updated.text.set(new_text.to_string());
         updated.edited_on = Some(LwwRegister::new(timestamp));
+        markup.apply_to(&mut updated);
+        attachments.apply_to(&mut updated);
 
         let _ = messages.update(index, updated.clone());
         Ok(updated)
@@ -1233,6 +4643,11 @@ impl MeroChat {
         let executor_id = Self::executor_id();
         let actor_role = self.role_of(&executor_id);
 
+        // Deleting either copy of a broadcast reply deletes the thread copy;
+        // `deleted_messages` hides the channel reference by the shared ID.
+        let broadcast_root = self.broadcast_root_of(&message_id);
+        let parent_id = parent_id.or_else(|| broadcast_root.clone());
+
         if let Some(parent_message_id) = parent_id {
             let mut thread_messages = match self.threads.get(&parent_message_id) {
                 Ok(Some(messages)) => messages,
@@ -1242,8 +4657,15 @@ impl MeroChat {
             Self::find_and_delete(&mut thread_messages, &message_id, &executor_id, actor_role)?;
             let _ = self.deleted_messages.insert(message_id.clone());
             let _ = self.reactions.remove(&message_id);
+            let _ = self.edit_history.remove(&message_id);
+            let _ = self.message_tags.remove(&message_id);
             let _ = self.threads.insert(parent_message_id, thread_messages);
 
+            if broadcast_root.is_some() {
+                app::emit!(Event::MessageSent(MessageSentEvent {
+                    message_id: message_id.clone(),
+                }));
+            }
             app::emit!(Event::MessageSentThread(MessageSentEvent {
                 message_id: message_id.clone(),
             }));
@@ -1252,6 +4674,8 @@ impl MeroChat {
             Self::find_and_delete(&mut self.messages, &message_id, &executor_id, actor_role)?;
             let _ = self.deleted_messages.insert(message_id.clone());
             let _ = self.reactions.remove(&message_id);
+            let _ = self.edit_history.remove(&message_id);
+            let _ = self.message_tags.remove(&message_id);
 
             app::emit!(Event::MessageSent(MessageSentEvent {
                 message_id: message_id.clone(),
@@ -1302,7 +4726,18 @@ impl MeroChat {
 
 #[cfg(test)]
 mod tests {
-    use super::{encode_blob_id_base58, parse_blob_id_base58, Role, BLOB_ID_SIZE};
+    use super::{
+        apply_notification_settings, attachments_to_remove, avatar_input_to_attachment,
+        delivery_status, encode_blob_id_base58, mention_diff, mentions_handle, normalize_keywords,
+        normalize_username, paginate_attachments, parse_blob_id_base58, parse_message_refs,
+        presence_status, text_matches_keywords, text_snippet, validate_media, validate_timezone,
+        validate_username, AttachmentEntry, AttachmentInput, AttachmentKind, AttachmentPage,
+        AttachmentPolicy, AttachmentPublic, BroadcastMentionPolicy, CustomStatus, DeliveryStatus,
+        ForwardPayload, ForwardProvenance, MediaMetadata, MeroChat, MessageMedia, MessageRefs,
+        NotificationSettings, NotifyLevel, PresenceStatus, Role, ThreadState, UnreadSummary,
+        UserId, AVATAR_MAX_SIZE, BLOB_ID_SIZE, MEDIA_WAVEFORM_MAX_POINTS, NOTIFY_KEYWORDS_MAX,
+        NOTIFY_KEYWORD_MAX_CHARS,
+    };
 
     // ── Role-based delete permission logic ─────────────────────────────────────
 
@@ -1344,6 +4779,646 @@ mod tests {
         assert!(!can_delete(sender, banned, Role::Banned));
     }
 
+    // ── Thread state permission logic ──────────────────────────────────────────
+
+    #[test]
+    fn author_can_resolve_and_reopen_own_thread() {
+        assert!(MeroChat::can_set_thread_state(
+            Role::User,
+            true,
+            ThreadState::Open,
+            ThreadState::Resolved,
+        ));
+        assert!(MeroChat::can_set_thread_state(
+            Role::User,
+            true,
+            ThreadState::Resolved,
+            ThreadState::Open,
+        ));
+    }
+
+    #[test]
+    fn author_cannot_lock_or_unlock() {
+        assert!(!MeroChat::can_set_thread_state(
+            Role::User,
+            true,
+            ThreadState::Open,
+            ThreadState::Locked,
+        ));
+        assert!(!MeroChat::can_set_thread_state(
+            Role::User,
+            true,
+            ThreadState::Locked,
+            ThreadState::Open,
+        ));
+    }
+
+    #[test]
+    fn non_author_user_cannot_resolve() {
+        assert!(!MeroChat::can_set_thread_state(
+            Role::User,
+            false,
+            ThreadState::Open,
+            ThreadState::Resolved,
+        ));
+    }
+
+    #[test]
+    fn mod_and_admin_can_lock_any_thread() {
+        assert!(MeroChat::can_set_thread_state(
+            Role::Mod,
+            false,
+            ThreadState::Open,
+            ThreadState::Locked,
+        ));
+        assert!(MeroChat::can_set_thread_state(
+            Role::Admin,
+            false,
+            ThreadState::Resolved,
+            ThreadState::Locked,
+        ));
+        assert!(MeroChat::can_set_thread_state(
+            Role::Mod,
+            false,
+            ThreadState::Locked,
+            ThreadState::Open,
+        ));
+    }
+
+    #[test]
+    fn banned_author_cannot_change_thread_state() {
+        assert!(!MeroChat::can_set_thread_state(
+            Role::Banned,
+            true,
+            ThreadState::Open,
+            ThreadState::Resolved,
+        ));
+    }
+
+    // ── Broadcast mention capability ───────────────────────────────────────────
+
+    #[test]
+    fn default_policy_limits_broadcast_mentions_to_moderators() {
+        let policy = BroadcastMentionPolicy::default();
+        assert!(!MeroChat::can_broadcast_mention(Role::User, policy, false));
+        assert!(MeroChat::can_broadcast_mention(Role::Mod, policy, false));
+        assert!(MeroChat::can_broadcast_mention(Role::Admin, policy, false));
+    }
+
+    #[test]
+    fn broadcast_mention_policy_levels() {
+        let everyone = BroadcastMentionPolicy::Everyone;
+        let admins = BroadcastMentionPolicy::Admins;
+        assert!(MeroChat::can_broadcast_mention(Role::User, everyone, false));
+        assert!(!MeroChat::can_broadcast_mention(Role::Mod, admins, false));
+        assert!(MeroChat::can_broadcast_mention(Role::Admin, admins, false));
+    }
+
+    #[test]
+    fn dm_members_can_always_broadcast_unless_banned() {
+        let admins = BroadcastMentionPolicy::Admins;
+        let everyone = BroadcastMentionPolicy::Everyone;
+        assert!(MeroChat::can_broadcast_mention(Role::User, admins, true));
+        assert!(!MeroChat::can_broadcast_mention(
+            Role::Banned,
+            everyone,
+            true
+        ));
+    }
+
+    // ── Mention / hashtag parsing ──────────────────────────────────────────────
+
+    #[test]
+    fn parses_mentions_and_hashtags() {
+        let refs = parse_message_refs("hey @alice and @bob.smith, see #Release-2 and #release-2.");
+        assert_eq!(refs.mentions, vec!["alice", "bob.smith"]);
+        assert_eq!(refs.hashtags, vec!["release-2"]);
+        assert!(refs.channels.is_empty());
+    }
+
+    #[test]
+    fn ignores_emails_issue_numbers_and_inline_code() {
+        let refs = parse_message_refs("mail a@b.com about #123, not `@carol #tag` @ # ok");
+        assert_eq!(refs, MessageRefs::default());
+    }
+
+    #[test]
+    fn base58_ids_are_channel_refs() {
+        let id = encode_blob_id_base58(&[7u8; BLOB_ID_SIZE]);
+        let refs = parse_message_refs(&format!("moved to #{} (@here)", id));
+        assert_eq!(refs.channels, vec![id]);
+        assert_eq!(refs.mentions, vec!["here"]);
+        assert!(refs.hashtags.is_empty());
+    }
+
+    #[test]
+    fn mentions_handle_matches_whole_names() {
+        assert!(mentions_handle("hi @Alice!", "alice"));
+        assert!(mentions_handle("ping @alice.", "alice"));
+        assert!(mentions_handle("cc @mary jane", "Mary Jane"));
+        assert!(!mentions_handle("hi @alice", "al"));
+        assert!(!mentions_handle("hi @alice.smith", "alice"));
+        assert!(!mentions_handle("mail bob@alice", "alice"));
+    }
+
+    #[test]
+    fn mention_diff_reports_changes() {
+        let alice = UserId::new([1u8; 32]);
+        let bob = UserId::new([2u8; 32]);
+        let carol = UserId::new([3u8; 32]);
+        let (added, removed) = mention_diff(&[alice, bob], &[bob, carol]);
+        assert_eq!(added, vec![carol]);
+        assert_eq!(removed, vec![alice]);
+        assert_eq!(mention_diff(&[alice], &[alice]), (vec![], vec![]));
+    }
+
+    // ── Reply preview snippets ─────────────────────────────────────────────────
+
+    #[test]
+    fn snippet_keeps_short_text_intact() {
+        assert_eq!(text_snippet("hello", 10), "hello");
+    }
+
+    #[test]
+    fn snippet_truncates_on_char_boundary_with_ellipsis() {
+        assert_eq!(text_snippet("héllo wörld", 5), "héllo…");
+    }
+
+    // ── Forward provenance digest ──────────────────────────────────────────────
+
+    fn sample_forward_payload() -> ForwardPayload {
+        let mut payload = ForwardPayload {
+            provenance: ForwardProvenance {
+                source_context_id: encode_blob_id_base58(&[7u8; BLOB_ID_SIZE]),
+                original_message_id: "abc_1".to_string(),
+                original_sender: UserId::new([1u8; 32]),
+                original_sender_username: "alice".to_string(),
+                original_timestamp: 1,
+                digest: String::new(),
+                verified: false,
+            },
+            text: "hello".to_string(),
+            files: Vec::new(),
+            images: Vec::new(),
+        };
+        payload.provenance.digest = payload.compute_digest();
+        payload
+    }
+
+    #[test]
+    fn forward_digest_is_stable() {
+        let payload = sample_forward_payload();
+        assert_eq!(payload.compute_digest(), payload.provenance.digest);
+        assert_eq!(payload.provenance.digest.len(), 64);
+    }
+
+    #[test]
+    fn forward_digest_detects_tampered_text_or_sender() {
+        let mut payload = sample_forward_payload();
+        payload.text = "hello!".to_string();
+        assert_ne!(payload.compute_digest(), payload.provenance.digest);
+
+        let mut payload = sample_forward_payload();
+        payload.provenance.original_sender = UserId::new([2u8; 32]);
+        assert_ne!(payload.compute_digest(), payload.provenance.digest);
+    }
+
+    #[test]
+    fn forward_digest_covers_attachment_fields() {
+        let attachment = AttachmentPublic {
+            name: "a.pdf".to_string(),
+            mime_type: "application/pdf".to_string(),
+            size: 10,
+            blob_id: encode_blob_id_base58(&[3u8; BLOB_ID_SIZE]),
+            uploaded_at: 0,
+            media: None,
+        };
+        let mut payload = sample_forward_payload();
+        payload.files.push(attachment.clone());
+        let digest = payload.compute_digest();
+
+        payload.files[0].size = 11;
+        assert_ne!(payload.compute_digest(), digest);
+
+        // Moving an attachment between files and images changes it too.
+        let mut moved = sample_forward_payload();
+        moved.images.push(attachment);
+        assert_ne!(moved.compute_digest(), digest);
+    }
+
+    // ── Username validation ────────────────────────────────────────────────────
+
+    #[test]
+    fn username_rejects_blank_and_overlong() {
+        assert!(validate_username("   ").is_err());
+        assert!(validate_username(&"a".repeat(51)).is_err());
+        assert!(validate_username("alice").is_ok());
+    }
+
+    #[test]
+    fn username_rejects_broadcast_names() {
+        assert!(validate_username("everyone").is_err());
+        assert!(validate_username(" HERE ").is_err());
+        assert!(validate_username("everyone2").is_ok());
+    }
+
+    #[test]
+    fn username_normalization_ignores_case_and_width() {
+        assert_eq!(normalize_username("Alice"), normalize_username("ALICE"));
+        assert_eq!(
+            normalize_username("Ａｌｉｃｅ"),
+            normalize_username("alice")
+        );
+        assert_eq!(normalize_username(" bob "), "bob");
+    }
+
+    #[test]
+    fn username_normalization_unifies_composed_forms() {
+        // "é" precomposed vs. "e" + combining acute accent.
+        assert_eq!(
+            normalize_username("Jos\u{e9}"),
+            normalize_username("Jose\u{301}")
+        );
+        assert_ne!(normalize_username("jose"), normalize_username("jos\u{e9}"));
+    }
+
+    // ── Rich profile fields ────────────────────────────────────────────────────
+
+    #[test]
+    fn timezone_accepts_iana_shapes() {
+        assert!(validate_timezone("UTC").is_ok());
+        assert!(validate_timezone("Europe/Berlin").is_ok());
+        assert!(validate_timezone("America/Argentina/Buenos_Aires").is_ok());
+        assert!(validate_timezone("Etc/GMT+5").is_ok());
+    }
+
+    #[test]
+    fn timezone_rejects_malformed_names() {
+        assert!(validate_timezone("").is_err());
+        assert!(validate_timezone("Europe/").is_err());
+        assert!(validate_timezone("Europe Berlin").is_err());
+    }
+
+    #[test]
+    fn status_is_hidden_when_cleared_or_expired() {
+        let status = CustomStatus {
+            emoji: "🌴".to_string(),
+            text: "On holiday".to_string(),
+            expires_at: Some(100),
+        };
+        assert!(status.is_active(99));
+        assert!(!status.is_active(100));
+        assert!(!CustomStatus::default().is_active(0));
+    }
+
+    // ── Attachment policy ──────────────────────────────────────────────────────
+
+    fn input(name: &str, mime_type: &str, size: u64) -> AttachmentInput {
+        AttachmentInput {
+            name: name.to_string(),
+            mime_type: mime_type.to_string(),
+            size,
+            blob_id_str: String::new(),
+            media: None,
+        }
+    }
+
+    #[test]
+    fn attachment_policy_matches_mime_wildcards() {
+        let policy = AttachmentPolicy {
+            allowed_mime_types: vec!["image/*".into(), "application/pdf".into()],
+            ..Default::default()
+        };
+        assert!(policy.allows_mime_type("image/png"));
+        assert!(policy.allows_mime_type("Application/PDF"));
+        assert!(!policy.allows_mime_type("application/zip"));
+        assert!(!policy.allows_mime_type("imagex/png"));
+        assert!(AttachmentPolicy::default().allows_mime_type("application/zip"));
+    }
+
+    #[test]
+    fn attachment_policy_enforces_size_count_and_quota() {
+        let policy = AttachmentPolicy {
+            allowed_mime_types: Vec::new(),
+            max_file_size: 100,
+            max_attachments_per_message: 2,
+            user_quota_bytes: 250,
+        };
+        let a = input("a", "text/plain", 60);
+        let b = input("b", "text/plain", 90);
+        let c = input("c", "x/y", 1);
+        assert_eq!(policy.check(&[&a, &b], 0), Ok(150));
+        assert!(policy.check(&[&a, &b, &c], 0).is_err());
+        assert!(policy
+            .check(&[&input("big", "text/plain", 101)], 0)
+            .is_err());
+        assert!(policy.check(&[&a, &b], 101).is_err());
+        assert_eq!(policy.check(&[&a, &b], 100), Ok(150));
+    }
+
+    fn entry(timestamp: u64, message_id: &str, index: u32) -> AttachmentEntry {
+        AttachmentEntry {
+            attachment: AttachmentPublic {
+                name: format!("{}-{}", message_id, index),
+                mime_type: "application/pdf".to_string(),
+                size: 1,
+                blob_id: String::new(),
+                uploaded_at: timestamp,
+                media: None,
+            },
+            kind: AttachmentKind::Files,
+            index,
+            message_id: message_id.to_string(),
+            parent_message_id: None,
+            sender: UserId::new([1u8; 32]),
+            sender_username: "alice".to_string(),
+            timestamp,
+        }
+    }
+
+    fn names(page: &AttachmentPage) -> Vec<String> {
+        page.attachments
+            .iter()
+            .map(|e| e.attachment.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn attachment_pages_follow_cursor_newest_first() {
+        let entries = vec![
+            entry(10, "m1", 0),
+            entry(30, "m3", 0),
+            entry(20, "m2", 1),
+            entry(20, "m2", 0),
+        ];
+
+        let first = paginate_attachments(entries.clone(), Some(2), None).unwrap();
+        assert_eq!(names(&first), vec!["m3-0", "m2-0"]);
+        let cursor = first.next_cursor.expect("more pages");
+
+        // A newer attachment arriving between calls doesn't shift the page.
+        let mut newer = entries.clone();
+        newer.push(entry(40, "m4", 0));
+        let second = paginate_attachments(newer, Some(2), Some(&cursor)).unwrap();
+        assert_eq!(names(&second), vec!["m2-1", "m1-0"]);
+        assert!(second.next_cursor.is_none());
+    }
+
+    #[test]
+    fn attachment_cursor_rejects_garbage() {
+        assert!(paginate_attachments(Vec::new(), None, Some("nope")).is_err());
+    }
+
+    fn public(blob_id: &str, size: u64) -> AttachmentPublic {
+        AttachmentPublic {
+            name: blob_id.to_string(),
+            mime_type: "image/png".to_string(),
+            size,
+            blob_id: blob_id.to_string(),
+            uploaded_at: 0,
+            media: None,
+        }
+    }
+
+    #[test]
+    fn attachment_removal_requires_existing_blobs() {
+        let current = vec![public("a", 1), public("b", 2)];
+        let removed = attachments_to_remove(&current, &["b".into(), "b".into()]).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].size, 2);
+        assert!(attachments_to_remove(&current, &["c".into()]).is_err());
+        assert!(attachments_to_remove(&current, &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn media_metadata_is_validated() {
+        assert!(validate_media(&MediaMetadata::default()).is_ok());
+        let zero_width = MediaMetadata {
+            width: Some(0),
+            ..Default::default()
+        };
+        assert!(validate_media(&zero_width).is_err());
+        let long_waveform = MediaMetadata {
+            waveform: Some(vec![0u8; MEDIA_WAVEFORM_MAX_POINTS + 1]),
+            ..Default::default()
+        };
+        assert!(validate_media(&long_waveform).is_err());
+        let bad_thumbnail = MediaMetadata {
+            thumbnail_blob_id: Some("not-a-blob".into()),
+            ..Default::default()
+        };
+        assert!(validate_media(&bad_thumbnail).is_err());
+    }
+
+    #[test]
+    fn message_media_is_indexed_by_kind_and_position() {
+        let voice = MediaMetadata {
+            duration_ms: Some(1_500),
+            ..Default::default()
+        };
+        let mut with_media = input("voice.ogg", "audio/ogg", 10);
+        with_media.media = Some(voice.clone());
+        let files = Some(vec![input("notes.txt", "text/plain", 10), with_media]);
+
+        let media = MessageMedia::from_inputs(&files, &None);
+        assert!(!media.is_empty());
+        assert_eq!(media.get(AttachmentKind::Files, 0), None);
+        assert_eq!(media.get(AttachmentKind::Files, 1), Some(voice));
+        assert_eq!(media.get(AttachmentKind::Images, 1), None);
+        assert!(MessageMedia::from_inputs(&None, &None).is_empty());
+    }
+
+    // ── Avatar validation ──────────────────────────────────────────────────────
+
+    fn avatar_input(mime_type: &str, size: u64) -> AttachmentInput {
+        AttachmentInput {
+            name: "me.png".to_string(),
+            mime_type: mime_type.to_string(),
+            size,
+            blob_id_str: encode_blob_id_base58(&[9u8; BLOB_ID_SIZE]),
+            media: None,
+        }
+    }
+
+    #[test]
+    fn avatar_rejects_non_image_mime_types() {
+        let err = avatar_input_to_attachment(avatar_input("text/html", 10), &[0u8; 32]);
+        assert!(err.unwrap_err().contains("Avatar must be one of"));
+    }
+
+    #[test]
+    fn avatar_rejects_empty_and_oversized_blobs() {
+        assert!(avatar_input_to_attachment(avatar_input("image/png", 0), &[0u8; 32]).is_err());
+        let too_big = avatar_input("image/png", AVATAR_MAX_SIZE + 1);
+        assert!(avatar_input_to_attachment(too_big, &[0u8; 32]).is_err());
+    }
+
+    // ── Presence thresholds ────────────────────────────────────────────────────
+
+    const SEC: u64 = 1_000_000_000;
+
+    #[test]
+    fn presence_without_heartbeat_is_offline() {
+        assert_eq!(
+            presence_status(None, 1_000 * SEC, 120, 900),
+            PresenceStatus::Offline
+        );
+    }
+
+    #[test]
+    fn presence_follows_thresholds() {
+        let now = 10_000 * SEC;
+        assert_eq!(
+            presence_status(Some(now - 60 * SEC), now, 120, 900),
+            PresenceStatus::Online
+        );
+        assert_eq!(
+            presence_status(Some(now - 600 * SEC), now, 120, 900),
+            PresenceStatus::Away
+        );
+        assert_eq!(
+            presence_status(Some(now - 901 * SEC), now, 120, 900),
+            PresenceStatus::Offline
+        );
+    }
+
+    #[test]
+    fn presence_tolerates_clock_skew() {
+        // A heartbeat from a node slightly ahead of ours must not underflow.
+        assert_eq!(
+            presence_status(Some(5 * SEC), 4 * SEC, 120, 900),
+            PresenceStatus::Online
+        );
+    }
+
+    // ── DM delivery status ─────────────────────────────────────────────────────
+
+    #[test]
+    fn delivery_status_progresses() {
+        assert_eq!(delivery_status(100, None, 0, true), DeliveryStatus::Sent);
+        assert_eq!(
+            delivery_status(100, Some(99 * SEC), 0, true),
+            DeliveryStatus::Sent
+        );
+        assert_eq!(
+            delivery_status(100, Some(100 * SEC), 0, true),
+            DeliveryStatus::Delivered
+        );
+        assert_eq!(
+            delivery_status(100, Some(100 * SEC), 100, true),
+            DeliveryStatus::Read
+        );
+    }
+
+    #[test]
+    fn delivery_status_hides_read_when_receipts_disabled() {
+        assert_eq!(
+            delivery_status(100, Some(200 * SEC), 150, false),
+            DeliveryStatus::Delivered
+        );
+        assert_eq!(delivery_status(100, None, 150, false), DeliveryStatus::Sent);
+    }
+
+    // ── Notification settings ──────────────────────────────────────────────────
+
+    fn unread(channel: u32, thread: u32, mentions: u32) -> UnreadSummary {
+        UnreadSummary {
+            channel_count: channel,
+            thread_count: thread,
+            mention_count: mentions,
+            alert_count: mentions,
+            oldest_unread_id: Some("m1".to_string()),
+        }
+    }
+
+    fn counts(summary: &UnreadSummary) -> (u32, u32, u32) {
+        (
+            summary.channel_count,
+            summary.thread_count,
+            summary.mention_count,
+        )
+    }
+
+    #[test]
+    fn notify_levels_filter_counts() {
+        let mut settings = NotificationSettings::default();
+        assert_eq!(
+            counts(&apply_notification_settings(unread(3, 2, 1), &settings, 0)),
+            (3, 2, 1)
+        );
+
+        settings.level = NotifyLevel::Mentions;
+        let summary = apply_notification_settings(unread(3, 2, 1), &settings, 0);
+        assert_eq!(counts(&summary), (0, 0, 1));
+        assert_eq!(summary.alert_count, 1);
+
+        settings.level = NotifyLevel::None;
+        let summary = apply_notification_settings(unread(3, 2, 1), &settings, 0);
+        assert_eq!(counts(&summary), (0, 0, 0));
+        assert_eq!(summary.alert_count, 0);
+        assert_eq!(summary.oldest_unread_id.as_deref(), Some("m1"));
+    }
+
+    #[test]
+    fn mute_expires() {
+        let settings = NotificationSettings {
+            muted_until: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(
+            counts(&apply_notification_settings(unread(3, 2, 1), &settings, 99)),
+            (0, 0, 0)
+        );
+        assert_eq!(
+            counts(&apply_notification_settings(
+                unread(3, 2, 1),
+                &settings,
+                100
+            )),
+            (3, 2, 1)
+        );
+    }
+
+    #[test]
+    fn thread_notifications_off_keeps_mentions() {
+        let settings = NotificationSettings {
+            thread_notifications: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            counts(&apply_notification_settings(unread(3, 2, 1), &settings, 0)),
+            (3, 0, 1)
+        );
+    }
+
+    #[test]
+    fn keywords_are_normalized() {
+        let keywords = vec![
+            " Incident ".into(),
+            "incident".into(),
+            "".into(),
+            "Team-A".into(),
+        ];
+        assert_eq!(
+            normalize_keywords(keywords).unwrap(),
+            vec!["incident", "team-a"]
+        );
+        assert!(normalize_keywords(vec!["x".repeat(NOTIFY_KEYWORD_MAX_CHARS + 1)]).is_err());
+        let too_many = (0..=NOTIFY_KEYWORDS_MAX)
+            .map(|i| format!("k{}", i))
+            .collect();
+        assert!(normalize_keywords(too_many).is_err());
+    }
+
+    #[test]
+    fn keyword_alerts_match_case_insensitively() {
+        let keywords = normalize_keywords(vec!["Incident".into(), "team-a".into()]).unwrap();
+        assert!(text_matches_keywords("New INCIDENT opened", &keywords));
+        assert!(text_matches_keywords("ping Team-A please", &keywords));
+        assert!(!text_matches_keywords("all quiet", &keywords));
+        assert!(!text_matches_keywords("incident", &[]));
+    }
+
     #[test]
     fn blob_id_roundtrip_typical() {
         let original: [u8; BLOB_ID_SIZE] = [